@@ -0,0 +1,34 @@
+#import bevy_sprite::mesh2d_view_bindings
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+
+    // Per instance data, see `BoidInstance`
+    @location(5) position_rotation: vec4<f32>,
+    @location(6) color: vec4<f32>,
+    @location(7) scale: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec4<f32>,
+};
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    let scaled = vertex.position.xy * vertex.scale;
+    let s = sin(vertex.position_rotation.w);
+    let c = cos(vertex.position_rotation.w);
+    let rotated = vec2<f32>(scaled.x * c - scaled.y * s, scaled.x * s + scaled.y * c);
+    let world_position = vec4<f32>(rotated + vertex.position_rotation.xy, vertex.position_rotation.z, 1.0);
+
+    var out: VertexOutput;
+    out.clip_position = view.view_proj * world_position;
+    out.color = vertex.color;
+    return out;
+}
+
+@fragment
+fn fragment(in: VertexOutput) -> @location(0) vec4<f32> {
+    return in.color;
+}
@@ -0,0 +1,165 @@
+//! Compares a tick of the structure of arrays core with the ECS core's flocking rules, run with
+//! the grid backend on the same flock.
+//!
+//! Both tick on the compute pool with the parallel feature, run with `--no-default-features` to
+//! compare them on one core. `cargo run --release -- --bench` compares the cores inside the
+//! actual app.
+
+use bevy::math::Vec2;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use bevy::tasks::{ComputeTaskPool, ParallelSlice, TaskPool};
+use boids::flow::{FlowField, FlowPattern};
+use boids::grid::{GridEntry, SpatialGrid};
+use boids::leader::{LeaderParams, LeaderSteering};
+use boids::rules::step_boid;
+use boids::soa::{SoaFlock, SoaParams};
+use boids::wander::WanderParams;
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const BOID_COUNTS: [usize; 4] = [1_000, 10_000, 50_000, 100_000];
+
+/// Same rules as the default options of the app.
+const PARAMS: SoaParams<'static> = SoaParams {
+    visibility_range: 10.0,
+    accuracy: 100,
+    separation: true,
+    separation_range: 2.0,
+    separation_impact: 0.05,
+    alignment: true,
+    alignment_impact: 0.05,
+    cohesion: true,
+    cohesion_impact: 0.0005,
+    border: true,
+    border_size: 50.0,
+    border_impact: 0.02,
+    border_center: Vec2::ZERO,
+    attractor: None,
+    attractor_impact: 0.01,
+    goals: &[],
+    leaders: LeaderParams {
+        weight: 1.0,
+        impact: 0.0,
+        steering: LeaderSteering::None,
+    },
+    wander: WanderParams {
+        seed: 0,
+        tick: 0,
+        impact: 0.0,
+        radius: 0.5,
+        rate: 0.02,
+        noise: 0.0,
+    },
+    flow: FlowField {
+        wind: Vec2::ZERO,
+        pattern: FlowPattern::None,
+        strength: 0.0,
+        scale: 1.0,
+        time: 0.0,
+        seed: 0,
+        grid: None,
+    },
+    speed_limit: true,
+    min_speed: 0.3,
+    max_speed: 0.2,
+};
+
+/// Random flock with the density of the default 100 boids.
+fn flock(count: usize) -> (Vec<GridEntry>, SoaParams<'static>) {
+    let mut rng = StdRng::seed_from_u64(0);
+    let border_size = (count as f32).sqrt() * 5.0;
+    let entries = (0..count)
+        .map(|_| GridEntry {
+            pos: Vec2::new(
+                rng.gen_range(-border_size..border_size),
+                rng.gen_range(-border_size..border_size),
+            ),
+            vel: Vec2::new(rng.gen_range(-0.2..0.2), rng.gen_range(-0.2..0.2)),
+            species: 0,
+            leader: false,
+        })
+        .collect();
+
+    (
+        entries,
+        SoaParams {
+            border_size,
+            ..PARAMS
+        },
+    )
+}
+
+/// Number of boids handed to each task, the same as the app's ECS core uses.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+const PARALLEL_CHUNK_SIZE: usize = 512;
+
+/// One tick of the ECS core with the grid backend, the flocking rules are applied one neighbor
+/// at a time through `step_boid` on the same threads the app uses.
+fn step_ecs(entries: &mut Vec<GridEntry>, params: &SoaParams) {
+    let grid = SpatialGrid::new(entries, params.visibility_range);
+    let update = |(id, entry): (usize, &GridEntry)| {
+        let stepped = step_boid(
+            id as u32,
+            entry,
+            params,
+            |visit| grid.for_each_within(entry.pos, params.visibility_range, visit),
+            None,
+        );
+
+        GridEntry {
+            pos: stepped.pos,
+            vel: stepped.vel,
+            ..*entry
+        }
+    };
+
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    let updated = entries
+        .iter()
+        .enumerate()
+        .collect::<Vec<_>>()
+        .par_chunk_map(ComputeTaskPool::get(), PARALLEL_CHUNK_SIZE, |chunk| {
+            chunk.iter().map(|it| update(*it)).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    let updated = entries.iter().enumerate().map(update).collect::<Vec<_>>();
+
+    *entries = updated;
+}
+
+fn bench_tick(c: &mut Criterion) {
+    // Both cores tick on the compute pool when the parallel feature is on
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    ComputeTaskPool::init(TaskPool::default);
+
+    let mut group = c.benchmark_group("tick");
+    group.sample_size(20);
+
+    for count in BOID_COUNTS {
+        let (entries, params) = flock(count);
+
+        group.bench_with_input(BenchmarkId::new("ecs", count), &count, |b, _| {
+            let mut entries = entries.clone();
+            b.iter(|| step_ecs(black_box(&mut entries), &params));
+        });
+
+        group.bench_with_input(BenchmarkId::new("soa", count), &count, |b, _| {
+            let mut soa = SoaFlock::default();
+            for (id, entry) in entries.iter().enumerate() {
+                soa.push(entry.pos, entry.vel, entry.species, id as u32, entry.leader);
+            }
+
+            b.iter(|| black_box(&mut soa).step(&params));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_tick);
+criterion_main!(benches);
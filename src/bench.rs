@@ -0,0 +1,121 @@
+use std::time::Instant;
+
+use bevy::app::AppExit;
+use bevy::prelude::{EventWriter, Local, Query, Res, ResMut};
+use bevy::time::Time;
+
+use crate::replay::{SimEvent, SimEvents};
+use crate::{headless_app, Boid, NeighborBackend, Options, SimulationCore};
+
+const BOID_COUNTS: [u32; 5] = [1_000, 5_000, 10_000, 25_000, 50_000];
+const WARMUP_TICKS: u32 = 10;
+const MEASURED_TICKS: u32 = 50;
+
+const RENDER_BOID_COUNTS: [u32; 5] = [1_000, 2_500, 5_000, 10_000, 20_000];
+const RENDER_WARMUP_FRAMES: u32 = 30;
+const RENDER_MEASURED_FRAMES: u32 = 120;
+
+/// Times whole simulation ticks with both neighbor backends and the structure of arrays core at
+/// increasing boid counts and prints the results as a table.
+pub fn run_neighbor_benchmark() {
+    println!("{:>8} {:>10} {:>10}", "Boids", "Backend", "ms/tick");
+    for count in BOID_COUNTS {
+        for backend in NeighborBackend::ALL {
+            let ms = time_ticks(count, SimulationCore::Ecs, backend);
+            println!("{count:>8} {:>10} {ms:>10.3}", backend.name());
+        }
+
+        let ms = time_ticks(count, SimulationCore::Soa, NeighborBackend::Grid);
+        println!("{count:>8} {:>10} {ms:>10.3}", SimulationCore::Soa.name());
+    }
+}
+
+fn time_ticks(count: u32, core: SimulationCore, backend: NeighborBackend) -> f64 {
+    // Growing the border with the count so every run has the density of the default 100 boids
+    let border_size = ((count as f32).sqrt() * 5.0) as i32;
+    let options = Options {
+        paused: false,
+        history: false,
+        simulation_core: core,
+        neighbor_backend: backend,
+        border_size,
+        ..Default::default()
+    };
+
+    let mut events = SimEvents::default();
+    events.push(SimEvent::spawn(count, &options));
+    let mut app = headless_app(options);
+    app.insert_resource(events);
+
+    // The first update only spawns the boids
+    for _ in 0..=WARMUP_TICKS {
+        app.update();
+    }
+
+    let start = Instant::now();
+    for _ in 0..MEASURED_TICKS {
+        app.update();
+    }
+
+    start.elapsed().as_secs_f64() * 1000.0 / MEASURED_TICKS as f64
+}
+
+#[derive(Default)]
+pub struct RenderBenchmark {
+    step: usize,
+    spawned: bool,
+    frames: u32,
+    elapsed: f32,
+    results: Vec<(u32, f32)>,
+}
+
+/// Raises the boid count step by step and averages the frame time at every count, then prints
+/// the results and exits.
+pub fn run_render_benchmark(
+    mut bench: Local<RenderBenchmark>,
+    mut events: ResMut<SimEvents>,
+    options: Res<Options>,
+    mut exit: EventWriter<AppExit>,
+    query: Query<&Boid>,
+    time: Res<Time>,
+) {
+    let Some(&target) = RENDER_BOID_COUNTS.get(bench.step) else {
+        return;
+    };
+
+    let count = query.iter().len() as u32;
+    if count < target {
+        if !bench.spawned {
+            events.push(SimEvent::spawn(target - count, &options));
+            bench.spawned = true;
+        }
+
+        return;
+    }
+
+    bench.frames += 1;
+    if bench.frames <= RENDER_WARMUP_FRAMES {
+        return;
+    }
+
+    bench.elapsed += time.delta_seconds();
+    if bench.frames < RENDER_WARMUP_FRAMES + RENDER_MEASURED_FRAMES {
+        return;
+    }
+
+    let frame_time = bench.elapsed / RENDER_MEASURED_FRAMES as f32 * 1000.0;
+    bench.results.push((count, frame_time));
+    bench.step += 1;
+    bench.spawned = false;
+    bench.frames = 0;
+    bench.elapsed = 0.0;
+
+    if bench.step == RENDER_BOID_COUNTS.len() {
+        println!("{:>8} {:>10}", "Boids", "ms/frame");
+        for (count, frame_time) in bench.results.iter() {
+            println!("{count:>8} {frame_time:>10.3}");
+        }
+
+        exit.send(AppExit);
+    }
+}
@@ -0,0 +1,383 @@
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+use bevy::ecs::system::SystemParam;
+use bevy::input::gamepad::{GamepadButton, GamepadButtonType, Gamepads};
+use bevy::prelude::{Input, KeyCode, MouseButton, Res, ResMut};
+use bevy_egui::{egui, EguiContext};
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+/// File the bindings are saved to natively.
+#[cfg(not(target_arch = "wasm32"))]
+const BINDINGS_FILE: &str = "bindings.ron";
+
+/// `localStorage` key the bindings are saved under on the web.
+#[cfg(target_arch = "wasm32")]
+const STORAGE_KEY: &str = "boids-bindings";
+
+/// Everything that can be bound to a key, mouse or gamepad button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    PanUp,
+    PanLeft,
+    PanDown,
+    PanRight,
+    /// Pans while held and the mouse moves
+    DragPan,
+    ZoomOut,
+    ZoomIn,
+    FitAll,
+    Select,
+    TogglePause,
+    Step,
+    FastForward,
+    Spawn,
+    RemoveAttractor,
+    LeaderUp,
+    LeaderLeft,
+    LeaderDown,
+    LeaderRight,
+}
+
+impl Action {
+    pub const ALL: [Self; 18] = [
+        Self::PanUp,
+        Self::PanLeft,
+        Self::PanDown,
+        Self::PanRight,
+        Self::DragPan,
+        Self::ZoomOut,
+        Self::ZoomIn,
+        Self::FitAll,
+        Self::Select,
+        Self::TogglePause,
+        Self::Step,
+        Self::FastForward,
+        Self::Spawn,
+        Self::RemoveAttractor,
+        Self::LeaderUp,
+        Self::LeaderLeft,
+        Self::LeaderDown,
+        Self::LeaderRight,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PanUp => "Pan Up",
+            Self::PanLeft => "Pan Left",
+            Self::PanDown => "Pan Down",
+            Self::PanRight => "Pan Right",
+            Self::DragPan => "Drag to Pan",
+            Self::ZoomOut => "Zoom Out",
+            Self::ZoomIn => "Zoom In",
+            Self::FitAll => "Fit All Boids",
+            Self::Select => "Select Boid",
+            Self::TogglePause => "Play / Pause",
+            Self::Step => "Step",
+            Self::FastForward => "Fast Forward",
+            Self::Spawn => "Spawn Boids",
+            Self::RemoveAttractor => "Remove Attractor",
+            Self::LeaderUp => "Steer Leaders Up",
+            Self::LeaderLeft => "Steer Leaders Left",
+            Self::LeaderDown => "Steer Leaders Down",
+            Self::LeaderRight => "Steer Leaders Right",
+        }
+    }
+
+    fn default_binding(self) -> Binding {
+        match self {
+            Self::PanUp => Binding::Key(KeyCode::W),
+            Self::PanLeft => Binding::Key(KeyCode::A),
+            Self::PanDown => Binding::Key(KeyCode::S),
+            Self::PanRight => Binding::Key(KeyCode::D),
+            Self::DragPan => Binding::Mouse(MouseButton::Right),
+            Self::ZoomOut => Binding::Key(KeyCode::Q),
+            Self::ZoomIn => Binding::Key(KeyCode::E),
+            Self::FitAll => Binding::Key(KeyCode::Home),
+            Self::Select => Binding::Mouse(MouseButton::Left),
+            Self::TogglePause => Binding::Key(KeyCode::Space),
+            Self::Step => Binding::Key(KeyCode::Period),
+            Self::FastForward => Binding::Key(KeyCode::F),
+            Self::Spawn => Binding::Key(KeyCode::N),
+            Self::RemoveAttractor => Binding::Key(KeyCode::X),
+            Self::LeaderUp => Binding::Key(KeyCode::I),
+            Self::LeaderLeft => Binding::Key(KeyCode::J),
+            Self::LeaderDown => Binding::Key(KeyCode::K),
+            Self::LeaderRight => Binding::Key(KeyCode::L),
+        }
+    }
+
+    /// Gamepad button bound next to the key, the sticks and triggers aren't actions.
+    fn default_gamepad_binding(self) -> Option<Binding> {
+        let button = match self {
+            Self::TogglePause => GamepadButtonType::South,
+            Self::Step => GamepadButtonType::RightTrigger,
+            Self::Spawn => GamepadButtonType::North,
+            Self::RemoveAttractor => GamepadButtonType::West,
+            _ => return None,
+        };
+
+        Some(Binding::Gamepad(button))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Pressed on any connected gamepad
+    Gamepad(GamepadButtonType),
+}
+
+impl Binding {
+    fn name(self) -> String {
+        match self {
+            Self::Key(key) => format!("{key:?}"),
+            Self::Mouse(button) => format!("{button:?} Mouse"),
+            Self::Gamepad(button) => format!("{button:?} Gamepad"),
+        }
+    }
+}
+
+/// Key or mouse button bound to every action, and the gamepad buttons bound alongside them,
+/// saved whenever one is changed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBindings {
+    keys: HashMap<Action, Binding>,
+    gamepad: HashMap<Action, Binding>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            keys: Action::ALL
+                .iter()
+                .map(|it| (*it, it.default_binding()))
+                .collect(),
+            gamepad: Action::ALL
+                .iter()
+                .filter_map(|it| Some((*it, it.default_gamepad_binding()?)))
+                .collect(),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn get(&self, action: Action) -> Binding {
+        self.keys
+            .get(&action)
+            .copied()
+            .unwrap_or_else(|| action.default_binding())
+    }
+
+    pub fn gamepad(&self, action: Action) -> Option<Binding> {
+        self.gamepad.get(&action).copied()
+    }
+
+    fn bind(&mut self, action: Action, binding: Binding) {
+        match binding {
+            Binding::Gamepad(_) => self.gamepad.insert(action, binding),
+            _ => self.keys.insert(action, binding),
+        };
+    }
+
+    /// Loads the saved bindings, falling back to the defaults for actions that weren't saved.
+    pub fn load() -> Self {
+        let mut bindings = Self::default();
+        let Some(contents) = read_saved() else {
+            return bindings;
+        };
+
+        match ron::from_str::<Self>(&contents) {
+            Ok(saved) => {
+                bindings.keys.extend(saved.keys);
+                bindings.gamepad.extend(saved.gamepad);
+            }
+            Err(e) => eprintln!("Error: Failed to parse the saved key bindings: {e}"),
+        }
+
+        bindings
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize key bindings: {e}"))?;
+        write_saved(&contents)
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn read_saved() -> Option<String> {
+    std::fs::read_to_string(BINDINGS_FILE).ok()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn write_saved(contents: &str) -> Result<(), String> {
+    std::fs::write(BINDINGS_FILE, contents)
+        .map_err(|e| format!("Failed to write {BINDINGS_FILE}: {e}"))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn read_saved() -> Option<String> {
+    local_storage()?.get_item(STORAGE_KEY).ok()?
+}
+
+#[cfg(target_arch = "wasm32")]
+fn write_saved(contents: &str) -> Result<(), String> {
+    local_storage()
+        .ok_or_else(|| "localStorage is unavailable".to_string())?
+        .set_item(STORAGE_KEY, contents)
+        .map_err(|e| format!("Failed to write to localStorage: {e:?}"))
+}
+
+/// State of the key binding window.
+#[derive(Debug, Default)]
+pub struct BindingEditor {
+    pub open: bool,
+    /// Action waiting for the next key, mouse or gamepad button to be bound to it
+    waiting: Option<Action>,
+}
+
+/// Input state looked up by action rather than by key. Nothing counts as pressed while a new
+/// binding is being picked.
+#[derive(SystemParam)]
+pub struct Actions<'w, 's> {
+    bindings: Res<'w, KeyBindings>,
+    editor: Res<'w, BindingEditor>,
+    keys: Res<'w, Input<KeyCode>>,
+    mouse: Res<'w, Input<MouseButton>>,
+    gamepads: Res<'w, Gamepads>,
+    buttons: Res<'w, Input<GamepadButton>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl Actions<'_, '_> {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.any_binding(action, |binding| match binding {
+            Binding::Key(key) => self.keys.pressed(key),
+            Binding::Mouse(button) => self.mouse.pressed(button),
+            Binding::Gamepad(button) => self.gamepad_button(button, Input::pressed),
+        })
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.any_binding(action, |binding| match binding {
+            Binding::Key(key) => self.keys.just_pressed(key),
+            Binding::Mouse(button) => self.mouse.just_pressed(button),
+            Binding::Gamepad(button) => self.gamepad_button(button, Input::just_pressed),
+        })
+    }
+
+    fn any_binding(&self, action: Action, f: impl Fn(Binding) -> bool) -> bool {
+        self.editor.waiting.is_none()
+            && (f(self.bindings.get(action)) || self.bindings.gamepad(action).map_or(false, f))
+    }
+
+    fn gamepad_button(
+        &self,
+        button: GamepadButtonType,
+        f: impl Fn(&Input<GamepadButton>, GamepadButton) -> bool,
+    ) -> bool {
+        self.gamepads
+            .iter()
+            .any(|gamepad| f(&self.buttons, GamepadButton(*gamepad, button)))
+    }
+}
+
+/// Binds the next key, mouse or gamepad button pressed to the action picked in the editor,
+/// Escape cancels. Gamepad buttons are bound next to the key rather than replacing it.
+pub fn capture_binding(
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    buttons: Res<Input<GamepadButton>>,
+    mut editor: ResMut<BindingEditor>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    let Some(action) = editor.waiting else {
+        return;
+    };
+
+    if keys.just_pressed(KeyCode::Escape) {
+        editor.waiting = None;
+        return;
+    }
+
+    let pressed = keys
+        .get_just_pressed()
+        .next()
+        .map(|it| Binding::Key(*it))
+        .or_else(|| {
+            mouse
+                .get_just_pressed()
+                .next()
+                .map(|it| Binding::Mouse(*it))
+        })
+        .or_else(|| {
+            buttons
+                .get_just_pressed()
+                .next()
+                .map(|it| Binding::Gamepad(it.1))
+        });
+    let Some(binding) = pressed else {
+        return;
+    };
+
+    editor.waiting = None;
+    bindings.bind(action, binding);
+    if let Err(e) = bindings.save() {
+        eprintln!("Error: {e}");
+    }
+}
+
+pub fn bindings_gui(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut editor: ResMut<BindingEditor>,
+    mut bindings: ResMut<KeyBindings>,
+) {
+    let mut open = editor.open;
+    egui::Window::new("Key Bindings")
+        .open(&mut open)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").show(ui, |ui| {
+                for action in Action::ALL {
+                    ui.label(action.name());
+
+                    let text = if editor.waiting == Some(action) {
+                        "Press a key...".to_string()
+                    } else {
+                        bindings.get(action).name()
+                    };
+                    if ui.button(text).clicked() {
+                        editor.waiting = Some(action);
+                    }
+
+                    if let Some(binding) = bindings.gamepad(action) {
+                        ui.label(binding.name());
+                    }
+
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            if ui.button("Reset to Defaults").clicked() {
+                *bindings = KeyBindings::default();
+                if let Err(e) = bindings.save() {
+                    eprintln!("Error: {e}");
+                }
+            }
+        });
+
+    editor.open = open;
+    if !open {
+        editor.waiting = None;
+    }
+}
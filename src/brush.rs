@@ -0,0 +1,215 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Local, Mesh, Query, Res, ResMut, Vec2, Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use bevy_egui::EguiContext;
+use boids::goal::{Goal, GoalKind};
+
+use crate::bindings::{Action, Actions};
+use crate::debug::circle;
+use crate::goal_ui::goal_color;
+use crate::input::CursorPosition;
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::replay::{SimEvent, SimEvents};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushTool {
+    Off,
+    Spawn,
+    Erase,
+    /// Places a goal on every click
+    Goal,
+}
+
+impl BrushTool {
+    pub const ALL: [Self; 4] = [Self::Off, Self::Spawn, Self::Erase, Self::Goal];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Spawn => "Spawn",
+            Self::Erase => "Erase",
+            Self::Goal => "Place Goal",
+        }
+    }
+}
+
+/// Direction painted boids start out flying in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BrushHeading {
+    Random,
+    /// Along the stroke, the first dab of a stroke has no direction yet and is random
+    Drag,
+    Fixed,
+}
+
+impl BrushHeading {
+    pub const ALL: [Self; 3] = [Self::Random, Self::Drag, Self::Fixed];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Random => "Random",
+            Self::Drag => "Drag Direction",
+            Self::Fixed => "Fixed",
+        }
+    }
+}
+
+/// Brush that paints boids into the world or erases them while the select binding is held.
+pub struct Brush {
+    pub tool: BrushTool,
+    pub radius: f32,
+    /// Boids spawned per square unit with every dab
+    pub density: f32,
+    pub heading: BrushHeading,
+    /// Heading in degrees used with [`BrushHeading::Fixed`]
+    pub fixed_heading: f32,
+    pub species: u8,
+    /// Goal placed by [`BrushTool::Goal`], at the cursor
+    pub goal: Goal,
+    /// Where the last dab of the current stroke was placed
+    last_dab: Option<Vec2>,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            tool: BrushTool::Off,
+            radius: 5.0,
+            density: 0.1,
+            heading: BrushHeading::Random,
+            fixed_heading: 0.0,
+            species: 0,
+            goal: GoalKind::Roost.preset(),
+            last_dab: None,
+        }
+    }
+}
+
+/// Marks the entity the brush outline is drawn through.
+#[derive(Component)]
+pub struct BrushMesh;
+
+pub fn spawn_brush_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the boids
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, 0.6))
+        .insert(BrushMesh);
+}
+
+/// Queues a dab whenever the cursor moved far enough along a stroke. Dabs go through the event
+/// queue so painted boids end up in recordings, and are spaced out so dragging slowly doesn't
+/// pile boids up.
+pub fn paint(
+    actions: Actions,
+    cursor: Res<CursorPosition>,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut brush: ResMut<Brush>,
+    mut events: ResMut<SimEvents>,
+) {
+    if brush.tool == BrushTool::Off || !actions.pressed(Action::Select) {
+        brush.last_dab = None;
+        return;
+    }
+
+    let Some(cursor) = cursor.0 else {
+        return;
+    };
+
+    // Strokes can't start on the GUI
+    if brush.last_dab.is_none() && egui_ctx.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
+    // Goals are placed once per click rather than all along the stroke
+    if brush.tool == BrushTool::Goal {
+        if brush.last_dab.is_none() {
+            events.push(SimEvent::PlaceGoal(Goal {
+                position: cursor.to_array(),
+                ..brush.goal.clone()
+            }));
+            brush.last_dab = Some(cursor);
+        }
+
+        return;
+    }
+
+    let direction = match brush.last_dab {
+        Some(last) if last.distance(cursor) < brush.radius * 0.5 => return,
+        Some(last) => Some(cursor - last),
+        None => None,
+    };
+    brush.last_dab = Some(cursor);
+
+    match brush.tool {
+        BrushTool::Spawn => {
+            let area = PI * brush.radius * brush.radius;
+            let heading = match brush.heading {
+                BrushHeading::Random => None,
+                BrushHeading::Drag => direction.map(|it| it.y.atan2(it.x)),
+                BrushHeading::Fixed => Some(brush.fixed_heading.to_radians()),
+            };
+
+            events.push(SimEvent::Paint {
+                center: cursor.to_array(),
+                radius: brush.radius,
+                count: ((area * brush.density).round() as u32).max(1),
+                heading,
+                species: brush.species,
+            });
+        }
+        BrushTool::Erase => events.push(SimEvent::Erase {
+            center: cursor.to_array(),
+            radius: brush.radius,
+        }),
+        BrushTool::Off | BrushTool::Goal => {}
+    }
+}
+
+/// Outlines the area the brush covers around the cursor.
+pub fn draw_brush(
+    mut brush_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<BrushMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    brush: Res<Brush>,
+    cursor: Res<CursorPosition>,
+    mut drawn: Local<Option<(BrushTool, Vec2, f32)>>,
+    mut lines: Local<LineMesh>,
+) {
+    let Ok((handle, mut visibility)) = brush_mesh.get_single_mut() else {
+        return;
+    };
+
+    let key = cursor
+        .0
+        .filter(|_| brush.tool != BrushTool::Off)
+        .map(|it| match brush.tool {
+            BrushTool::Goal => (brush.tool, it, brush.goal.radius),
+            _ => (brush.tool, it, brush.radius),
+        });
+    if *drawn == key {
+        return;
+    }
+
+    *drawn = key;
+
+    lines.clear();
+    if let Some((tool, pos, radius)) = key {
+        let color = match tool {
+            BrushTool::Erase => Color::rgba(1.0, 0.3, 0.3, 0.8),
+            BrushTool::Goal => goal_color(brush.goal.kind),
+            _ => Color::rgba(1.0, 1.0, 1.0, 0.8),
+        };
+        circle(&mut lines, pos, radius, radius * 0.03, color);
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Entity, Local, Mesh, Query, Res, ResMut, Transform, Vec2,
+    Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use boids::rules::Forces;
+
+use crate::inspect::Selection;
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::{Boid, Options, State};
+
+const BORDER_COLOR: Color = Color::rgb(1.0, 0.9, 0.2);
+
+/// Name and color of every force, in the order of [`Forces::all`].
+pub const FORCE_LEGEND: [(&str, Color); 8] = [
+    ("Separation", Color::rgb(1.0, 0.25, 0.25)),
+    ("Alignment", Color::rgb(0.3, 0.5, 1.0)),
+    ("Cohesion", Color::rgb(0.3, 1.0, 0.4)),
+    ("Border", BORDER_COLOR),
+    ("Attractor", Color::rgb(1.0, 0.6, 0.1)),
+    ("Wander", Color::rgb(0.2, 0.9, 0.9)),
+    ("Speed Limit", Color::rgb(1.0, 0.3, 1.0)),
+    ("Noise", Color::rgb(0.75, 0.75, 0.75)),
+];
+
+/// Number of segments circles are drawn with.
+const CIRCLE_SEGMENTS: usize = 32;
+
+/// Forces of the last tick per boid. Only filled while something shows them, breaking every
+/// velocity change down costs an allocation per boid.
+#[derive(Default)]
+pub struct BoidForces {
+    forces: HashMap<Entity, Forces>,
+    /// Boid being inspected, its forces are recorded even with every overlay turned off
+    watched: Option<Entity>,
+}
+
+impl BoidForces {
+    pub fn records(&self, entity: Entity, options: &Options) -> bool {
+        let overlays =
+            (options.debug_forces || options.debug_neighbors) && !options.debug_only_selected;
+        overlays || self.watched == Some(entity)
+    }
+
+    pub fn watched(&self) -> Option<Entity> {
+        self.watched
+    }
+
+    pub fn watch(&mut self, entity: Option<Entity>) {
+        self.watched = entity;
+    }
+
+    pub fn set(&mut self, forces: impl Iterator<Item = (Entity, Forces)>) {
+        self.forces.clear();
+        self.forces.extend(forces);
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&Forces> {
+        self.forces.get(&entity)
+    }
+}
+
+/// Marks the entity the debug overlays are drawn through.
+#[derive(Component)]
+pub struct DebugMesh;
+
+pub fn spawn_debug_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the boids
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, 0.5))
+        .insert(DebugMesh);
+}
+
+/// Draws perception ranges, used neighbors, forces and the border, whichever are enabled.
+pub fn draw_debug_overlays(
+    query: Query<(Entity, &Transform), With<Boid>>,
+    mut debug_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<DebugMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    forces: Res<BoidForces>,
+    options: Res<Options>,
+    state: Res<State>,
+    selection: Res<Selection>,
+    mut lines: Local<LineMesh>,
+) {
+    let Ok((handle, mut visibility)) = debug_mesh.get_single_mut() else {
+        return;
+    };
+
+    let enabled = options.debug_ranges
+        || options.debug_neighbors
+        || options.debug_forces
+        || options.debug_border;
+    if !enabled {
+        if visibility.is_visible {
+            visibility.is_visible = false;
+        }
+
+        return;
+    }
+
+    let width = options.debug_line_width;
+    lines.clear();
+
+    if options.debug_border {
+        let size = Vec2::splat(options.border_size as f32);
+        let min = state.border_center - size;
+        let max = state.border_center + size;
+        let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+        for i in 0..corners.len() {
+            let (from, to) = (corners[i], corners[(i + 1) % corners.len()]);
+            lines.segment(from, to, width * 2.0, BORDER_COLOR, BORDER_COLOR);
+        }
+    }
+
+    for (entity, transform) in query.iter() {
+        if options.debug_only_selected && selection.0 != Some(entity) {
+            continue;
+        }
+
+        let pos = transform.translation.truncate();
+
+        if options.debug_ranges {
+            let visibility_color = Color::rgba(1.0, 1.0, 1.0, 0.3);
+            let separation_color = Color::rgba(1.0, 0.25, 0.25, 0.5);
+
+            circle(
+                &mut lines,
+                pos,
+                options.visibility_range,
+                width,
+                visibility_color,
+            );
+            // The separation range is compared against the squared distance
+            circle(
+                &mut lines,
+                pos,
+                options.separation_range.sqrt(),
+                width,
+                separation_color,
+            );
+        }
+
+        let Some(forces) = forces.get(entity) else { continue; };
+
+        if options.debug_neighbors {
+            let color = Color::rgba(0.8, 0.8, 0.8, 0.4);
+            for neighbor in forces.neighbors.iter() {
+                lines.segment(pos, *neighbor, width, color, color);
+            }
+        }
+
+        if options.debug_forces {
+            for (force, (_, color)) in forces.all().into_iter().zip(FORCE_LEGEND) {
+                lines.arrow(pos, pos + force * options.debug_force_scale, width, color);
+            }
+        }
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
+
+/// Adds the outline of a circle.
+pub fn circle(lines: &mut LineMesh, center: Vec2, radius: f32, width: f32, color: Color) {
+    let point = |i: usize| {
+        let angle = i as f32 / CIRCLE_SEGMENTS as f32 * TAU;
+        center + Vec2::new(angle.cos(), angle.sin()) * radius
+    };
+
+    for i in 0..CIRCLE_SEGMENTS {
+        lines.segment(point(i), point(i + 1), width, color, color);
+    }
+}
@@ -0,0 +1,204 @@
+//! Wind and flow fields that carry boids along, sampled the same way by both simulation cores.
+
+use std::f32::consts::{PI, SQRT_2, TAU};
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Spatially varying part of the flow, added on top of the wind.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FlowPattern {
+    #[default]
+    None,
+    /// Curl of gradient noise, swirling without sources or sinks
+    Curl,
+    /// Grid of counter rotating vortices
+    Vortices,
+    /// [`VectorGrid`] loaded from a file
+    Loaded,
+}
+
+impl FlowPattern {
+    pub const ALL: [Self; 4] = [Self::None, Self::Curl, Self::Vortices, Self::Loaded];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Curl => "Curl Noise",
+            Self::Vortices => "Vortices",
+            Self::Loaded => "Loaded",
+        }
+    }
+}
+
+/// Vectors on a regular grid, read from RON like
+/// `(origin: (-50.0, -50.0), cell_size: 10.0, width: 11, height: 11, vectors: [(0.1, 0.0), ...])`
+/// with the vectors in rows from the bottom left. Sampled bilinearly and clamped to the edges.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VectorGrid {
+    pub origin: [f32; 2],
+    pub cell_size: f32,
+    pub width: usize,
+    pub height: usize,
+    pub vectors: Vec<[f32; 2]>,
+}
+
+impl VectorGrid {
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        let grid: Self = ron::from_str(contents).map_err(|e| e.to_string())?;
+        if grid.width == 0 || grid.height == 0 || grid.vectors.len() != grid.width * grid.height {
+            return Err(format!(
+                "Expected {} by {} vectors, found {}",
+                grid.width,
+                grid.height,
+                grid.vectors.len()
+            ));
+        }
+
+        if grid.cell_size <= 0.0 {
+            return Err("The cell size has to be positive".to_owned());
+        }
+
+        Ok(grid)
+    }
+
+    pub fn sample(&self, pos: Vec2) -> Vec2 {
+        let max = Vec2::new((self.width - 1) as f32, (self.height - 1) as f32);
+        let cell = ((pos - Vec2::from(self.origin)) / self.cell_size).clamp(Vec2::ZERO, max);
+        let (x, y) = (cell.x as usize, cell.y as usize);
+        let (x1, y1) = ((x + 1).min(self.width - 1), (y + 1).min(self.height - 1));
+        let f = cell - Vec2::new(x as f32, y as f32);
+
+        let at = |x: usize, y: usize| Vec2::from(self.vectors[y * self.width + x]);
+        let bottom = at(x, y).lerp(at(x1, y), f.x);
+        let top = at(x, y1).lerp(at(x1, y1), f.x);
+        bottom.lerp(top, f.y)
+    }
+}
+
+/// Drift added to the position of every boid on top of its own velocity.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FlowField<'a> {
+    /// Drift that's the same everywhere
+    pub wind: Vec2,
+    pub pattern: FlowPattern,
+    pub strength: f32,
+    /// Size of the features of the pattern in units
+    pub scale: f32,
+    /// Phase the pattern has evolved to, advances by 1 per pattern cycle
+    pub time: f32,
+    pub seed: u32,
+    pub grid: Option<&'a VectorGrid>,
+}
+
+impl FlowField<'_> {
+    pub fn sample(&self, pos: Vec2) -> Vec2 {
+        let flow = match self.pattern {
+            FlowPattern::None => return self.wind,
+            FlowPattern::Curl => self.curl(pos / self.scale),
+            FlowPattern::Vortices => {
+                let p = pos / self.scale * PI;
+                let x = p.x + self.time * TAU;
+                Vec2::new(x.sin() * p.y.cos(), -x.cos() * p.y.sin())
+            }
+            FlowPattern::Loaded => self.grid.map_or(Vec2::ZERO, |it| it.sample(pos)),
+        };
+
+        self.wind + flow * self.strength
+    }
+
+    /// Rotated gradient of the noise, found with central differences.
+    fn curl(&self, p: Vec2) -> Vec2 {
+        const EPSILON: f32 = 0.01;
+
+        let dx = self.noise(p + Vec2::X * EPSILON) - self.noise(p - Vec2::X * EPSILON);
+        let dy = self.noise(p + Vec2::Y * EPSILON) - self.noise(p - Vec2::Y * EPSILON);
+        Vec2::new(dy, -dx) / (2.0 * EPSILON)
+    }
+
+    /// Noise evolving over time by blending between independent slices.
+    fn noise(&self, p: Vec2) -> f32 {
+        let slice = self.time.floor();
+        let f = self.time - slice;
+        let seed = self.seed.wrapping_add(slice as i32 as u32);
+        let a = gradient_noise(p, seed);
+        let b = gradient_noise(p, seed.wrapping_add(1));
+        a + (b - a) * f
+    }
+}
+
+/// Perlin style gradient noise, roughly between -1 and 1.
+fn gradient_noise(p: Vec2, seed: u32) -> f32 {
+    let cell = p.floor();
+    let f = p - cell;
+    let (x, y) = (cell.x as i32, cell.y as i32);
+
+    let corner = |cx: i32, cy: i32| {
+        let angle = hash(cx, cy, seed) as f32 / u32::MAX as f32 * TAU;
+        let gradient = Vec2::new(angle.cos(), angle.sin());
+        gradient.dot(f - Vec2::new((cx - x) as f32, (cy - y) as f32))
+    };
+
+    // Quintic fade so the field has continuous derivatives across cells
+    let fade = f * f * f * (f * (f * 6.0 - 15.0) + 10.0);
+    let bottom = corner(x, y) + (corner(x + 1, y) - corner(x, y)) * fade.x;
+    let top = corner(x, y + 1) + (corner(x + 1, y + 1) - corner(x, y + 1)) * fade.x;
+    (bottom + (top - bottom) * fade.y) * SQRT_2
+}
+
+fn hash(x: i32, y: i32, seed: u32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x27D4_EB2D) ^ (y as u32).wrapping_mul(0x1656_67B1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x2C1B_3C6D);
+    h ^= h >> 12;
+    h = h.wrapping_mul(0x297A_2D39);
+    h ^ (h >> 15)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+
+    use super::VectorGrid;
+
+    #[test]
+    fn parses_a_grid() {
+        let grid = VectorGrid::parse(
+            "(origin: (-10.0, 0.0), cell_size: 10.0, width: 2, height: 2, \
+             vectors: [(1.0, 0.0), (0.0, 1.0), (-1.0, 0.0), (0.0, -1.0)])",
+        )
+        .unwrap();
+
+        assert_eq!(grid.width, 2);
+        assert_eq!(grid.vectors[3], [0.0, -1.0]);
+        assert_eq!(grid.sample(Vec2::new(-10.0, 0.0)), Vec2::new(1.0, 0.0));
+        // Clamped to the top right corner past the edges
+        assert_eq!(grid.sample(Vec2::new(100.0, 100.0)), Vec2::new(0.0, -1.0));
+    }
+
+    #[test]
+    fn rejects_grids_without_a_vector_per_cell() {
+        let short = "(origin: (0.0, 0.0), cell_size: 1.0, width: 2, height: 2, \
+                     vectors: [(1.0, 0.0), (0.0, 1.0), (1.0, 1.0)])";
+        let long = "(origin: (0.0, 0.0), cell_size: 1.0, width: 1, height: 1, \
+                    vectors: [(1.0, 0.0), (0.0, 1.0)])";
+        let empty = "(origin: (0.0, 0.0), cell_size: 1.0, width: 0, height: 3, vectors: [])";
+        for contents in [short, long, empty] {
+            assert!(VectorGrid::parse(contents).is_err(), "{contents}");
+        }
+    }
+
+    #[test]
+    fn rejects_cells_without_a_size() {
+        let contents = "(origin: (0.0, 0.0), cell_size: 0.0, width: 1, height: 1, \
+                        vectors: [(1.0, 0.0)])";
+        assert!(VectorGrid::parse(contents).is_err());
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for contents in ["", "not a grid", "(origin: (0.0, 0.0))", "[1, 2, 3]"] {
+            assert!(VectorGrid::parse(contents).is_err(), "{contents}");
+        }
+    }
+}
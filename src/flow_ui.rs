@@ -0,0 +1,218 @@
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Local, Mesh, Query, Res, ResMut, Vec2, Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use bevy_egui::{egui, EguiContext};
+use boids::flow::{FlowField, FlowPattern, VectorGrid};
+
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::replay::{SimEvent, SimEvents};
+use crate::{Options, State, TICK_RATE};
+
+/// At most this many arrows are drawn along each side, the spacing grows past that.
+const MAX_ARROWS_PER_SIDE: f32 = 100.0;
+
+/// Field of [`FlowPattern::Loaded`]. Kept out of the options so that it isn't copied into every
+/// recorded options change, loading one is recorded as its own event instead.
+#[derive(Debug, Default)]
+pub struct FlowGrid(pub Option<VectorGrid>);
+
+/// Flow the boids drift along on the current tick.
+pub fn flow_field<'a>(options: &Options, state: &State, grid: &'a FlowGrid) -> FlowField<'a> {
+    FlowField {
+        wind: Vec2::from(options.wind),
+        pattern: options.flow_pattern,
+        strength: options.flow_strength,
+        scale: options.flow_scale,
+        time: (state.tick as f64 / TICK_RATE * options.flow_evolution as f64) as f32,
+        seed: options.seed as u32,
+        grid: grid.0.as_ref(),
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct FlowGui {
+    path: String,
+    status: String,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Default for FlowGui {
+    fn default() -> Self {
+        Self {
+            path: "flow.ron".to_owned(),
+            status: String::new(),
+        }
+    }
+}
+
+pub fn flow_gui(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut options: ResMut<Options>,
+    grid: Res<FlowGrid>,
+    #[cfg(not(target_arch = "wasm32"))] mut events: ResMut<SimEvents>,
+    #[cfg(not(target_arch = "wasm32"))] mut gui: Local<FlowGui>,
+) {
+    egui::Window::new("Wind")
+        .default_open(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Wind");
+                ui.add(egui::DragValue::new(&mut options.wind[0]).speed(0.001));
+                ui.add(egui::DragValue::new(&mut options.wind[1]).speed(0.001));
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Flow Field");
+                egui::ComboBox::from_id_source("flow_pattern")
+                    .selected_text(options.flow_pattern.name())
+                    .show_ui(ui, |ui| {
+                        for pattern in FlowPattern::ALL {
+                            ui.selectable_value(&mut options.flow_pattern, pattern, pattern.name());
+                        }
+                    });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Strength");
+                ui.add(
+                    egui::DragValue::new(&mut options.flow_strength)
+                        .speed(0.001)
+                        .clamp_range(0.0..=1.0),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Scale");
+                ui.add(egui::DragValue::new(&mut options.flow_scale).clamp_range(1.0..=1000.0));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Evolution");
+                ui.add(
+                    egui::DragValue::new(&mut options.flow_evolution)
+                        .speed(0.01)
+                        .suffix("/s")
+                        .clamp_range(0.0..=10.0),
+                );
+            });
+
+            if options.flow_pattern == FlowPattern::Loaded && grid.0.is_none() {
+                ui.label("No field loaded, boids only drift with the wind");
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                // The web build has no file system to load fields from
+                ui.text_edit_singleline(&mut gui.path);
+                if ui.button("Load Field").clicked() {
+                    let path = PathBuf::from(&gui.path);
+                    let grid = std::fs::read_to_string(&path)
+                        .map_err(|e| e.to_string())
+                        .and_then(|it| VectorGrid::parse(&it));
+
+                    gui.status = match grid {
+                        Ok(grid) => {
+                            events.push(SimEvent::LoadFlowGrid(Some(grid)));
+                            options.flow_pattern = FlowPattern::Loaded;
+                            format!("Loaded {}", path.display())
+                        }
+                        Err(e) => format!("Failed to load {}: {e}", path.display()),
+                    };
+                }
+
+                if !gui.status.is_empty() {
+                    ui.label(&gui.status);
+                }
+            }
+
+            ui.separator();
+            ui.checkbox(&mut options.flow_arrows, "Show Flow");
+            ui.horizontal(|ui| {
+                ui.label("Arrow Spacing");
+                ui.add(
+                    egui::DragValue::new(&mut options.flow_arrow_spacing).clamp_range(1.0..=100.0),
+                );
+            });
+        });
+}
+
+/// Marks the entity the flow arrows are drawn through.
+#[derive(Component)]
+pub struct FlowMesh;
+
+pub fn spawn_flow_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the heatmap and under the trails
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, -0.75))
+        .insert(FlowMesh);
+}
+
+/// Draws a grid of arrows along the flow over the border and a bit around it, scaled so the
+/// strongest one spans most of its cell.
+pub fn draw_flow_arrows(
+    mut flow_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<FlowMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    options: Res<Options>,
+    state: Res<State>,
+    grid: Res<FlowGrid>,
+    mut lines: Local<LineMesh>,
+) {
+    let Ok((handle, mut visibility)) = flow_mesh.get_single_mut() else {
+        return;
+    };
+
+    if !options.flow_arrows {
+        if visibility.is_visible {
+            visibility.is_visible = false;
+        }
+
+        return;
+    }
+
+    if !options.is_changed() && !state.is_changed() && !grid.is_changed() {
+        return;
+    }
+
+    let extent = options.border_size as f32 * 1.5;
+    let spacing = options
+        .flow_arrow_spacing
+        .max(extent * 2.0 / MAX_ARROWS_PER_SIDE);
+    let count = (extent * 2.0 / spacing) as i32;
+    let min = state.border_center - Vec2::splat(extent);
+
+    let flow = flow_field(&options, &state, &grid);
+    let samples = (0..count * count)
+        .map(|i| {
+            let center = min + (Vec2::new((i % count) as f32, (i / count) as f32) + 0.5) * spacing;
+            (center, flow.sample(center))
+        })
+        .collect::<Vec<_>>();
+    let strongest = samples
+        .iter()
+        .fold(0.0_f32, |max, (_, it)| max.max(it.length()));
+
+    lines.clear();
+    if strongest > 0.0 {
+        let color = Color::rgba(0.6, 0.9, 1.0, 0.5);
+        for (center, drift) in samples {
+            let offset = drift / strongest * spacing * 0.4;
+            lines.arrow(center - offset, center + offset, spacing * 0.06, color);
+        }
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
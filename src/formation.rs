@@ -0,0 +1,217 @@
+use std::f32::consts::TAU;
+
+use bevy::prelude::Vec2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Arrangement newly spawned boids start out in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Formation {
+    /// Spread evenly over the border square
+    #[default]
+    Square,
+    /// Spread evenly over the circle within the border
+    Disc,
+    /// Normally distributed around the center
+    Cluster,
+    /// On a circle, flying along it so the flock starts out milling
+    Ring,
+    /// Two flocks on either side flying towards each other
+    Opposing,
+    /// On a regular grid
+    Lattice,
+    /// Spread evenly over the border square, all heading the same way
+    Aligned,
+}
+
+impl Formation {
+    pub const ALL: [Self; 7] = [
+        Self::Square,
+        Self::Disc,
+        Self::Cluster,
+        Self::Ring,
+        Self::Opposing,
+        Self::Lattice,
+        Self::Aligned,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Square => "Uniform Square",
+            Self::Disc => "Uniform Disc",
+            Self::Cluster => "Gaussian Cluster",
+            Self::Ring => "Milling Ring",
+            Self::Opposing => "Opposing Flocks",
+            Self::Lattice => "Lattice",
+            Self::Aligned => "All Aligned",
+        }
+    }
+
+    /// Positions and velocities of `count` boids within `extent` of the origin, flying at
+    /// `speed`.
+    pub fn place<R: Rng>(
+        self,
+        rng: &mut R,
+        count: u32,
+        extent: f32,
+        speed: f32,
+    ) -> Vec<(Vec2, Vec2)> {
+        let random_velocity = |rng: &mut R| unit(random_angle(rng)) * speed;
+
+        match self {
+            Self::Square => (0..count)
+                .map(|_| {
+                    let pos = random_in_square(rng, extent);
+                    (pos, random_velocity(rng))
+                })
+                .collect(),
+            Self::Disc => (0..count)
+                .map(|_| {
+                    // Taking the square root spreads the boids evenly over the circle
+                    let distance = extent * rng.gen::<f32>().sqrt();
+                    let pos = unit(random_angle(rng)) * distance;
+                    (pos, random_velocity(rng))
+                })
+                .collect(),
+            Self::Cluster => (0..count)
+                .map(|_| {
+                    // Box-Muller transform, the first sample is kept above 0 for the logarithm.
+                    // The few boids more than four deviations out are kept on the edge.
+                    let sample = 1.0 - rng.gen::<f32>();
+                    let distance = ((-2.0 * sample.ln()).sqrt() * extent * 0.25).min(extent);
+                    let pos = unit(random_angle(rng)) * distance;
+                    (pos, random_velocity(rng))
+                })
+                .collect(),
+            Self::Ring => (0..count)
+                .map(|_| {
+                    let angle = random_angle(rng);
+                    let distance = extent * (0.65 + rng.gen::<f32>() * 0.1);
+                    let dir = unit(angle);
+                    (dir * distance, dir.perp() * speed)
+                })
+                .collect(),
+            Self::Opposing => (0..count)
+                .map(|i| {
+                    // Every other boid joins the flock on the right
+                    let side = if i % 2 == 0 { -1.0 } else { 1.0 };
+                    let x = side * extent * (0.4 + rng.gen::<f32>() * 0.5);
+                    let y = (rng.gen::<f32>() * 2.0 - 1.0) * extent * 0.5;
+                    (Vec2::new(x, y), Vec2::new(-side * speed, 0.0))
+                })
+                .collect(),
+            Self::Lattice => {
+                let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+                let spacing = extent * 2.0 / columns as f32;
+                (0..count)
+                    .map(|i| {
+                        let cell = Vec2::new((i % columns) as f32, (i / columns) as f32);
+                        let pos = (cell + 0.5) * spacing - extent;
+                        (pos, random_velocity(rng))
+                    })
+                    .collect()
+            }
+            Self::Aligned => {
+                let vel = random_velocity(rng);
+                (0..count)
+                    .map(|_| (random_in_square(rng, extent), vel))
+                    .collect()
+            }
+        }
+    }
+}
+
+fn random_angle(rng: &mut impl Rng) -> f32 {
+    rng.gen::<f32>() * TAU
+}
+
+fn random_in_square(rng: &mut impl Rng, extent: f32) -> Vec2 {
+    let x = rng.gen::<f32>() * extent * 2.0 - extent;
+    let y = rng.gen::<f32>() * extent * 2.0 - extent;
+    Vec2::new(x, y)
+}
+
+fn unit(angle: f32) -> Vec2 {
+    Vec2::new(angle.cos(), angle.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec2;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::Formation;
+
+    const EXTENT: f32 = 50.0;
+    const SPEED: f32 = 0.25;
+
+    fn place(formation: Formation, count: u32) -> Vec<(Vec2, Vec2)> {
+        formation.place(&mut StdRng::seed_from_u64(3), count, EXTENT, SPEED)
+    }
+
+    #[test]
+    fn places_every_boid_within_the_extent_at_the_speed() {
+        for formation in Formation::ALL {
+            for count in [0, 1, 2, 7, 100, 1000] {
+                let boids = place(formation, count);
+                assert_eq!(boids.len(), count as usize, "{formation:?}");
+                for (pos, vel) in boids {
+                    assert!(
+                        pos.abs().max_element() <= EXTENT,
+                        "{formation:?} placed a boid at {pos}"
+                    );
+                    assert!(
+                        (vel.length() - SPEED).abs() < 1e-5,
+                        "{formation:?} gave a boid a velocity of {vel}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lattice_puts_every_boid_on_its_own_point() {
+        for count in [1, 2, 9, 10, 99] {
+            let boids = place(Formation::Lattice, count);
+            for (i, (a, _)) in boids.iter().enumerate() {
+                for (b, _) in boids[i + 1..].iter() {
+                    assert!(a.distance(*b) > 1.0, "{count} boids overlap at {a}");
+                }
+            }
+        }
+
+        // A square number of boids fills the whole square, centered
+        let boids = place(Formation::Lattice, 4);
+        let positions = boids.iter().map(|it| it.0).collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                Vec2::new(-25.0, -25.0),
+                Vec2::new(25.0, -25.0),
+                Vec2::new(-25.0, 25.0),
+                Vec2::new(25.0, 25.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn opposing_flocks_fly_towards_each_other() {
+        let boids = place(Formation::Opposing, 101);
+        let left = boids.iter().filter(|(pos, _)| pos.x < 0.0).count();
+        assert_eq!(left, 51);
+        for (pos, vel) in boids {
+            assert_eq!(vel.y, 0.0);
+            assert!(
+                pos.x * vel.x < 0.0,
+                "the boid at {pos} flies away with {vel}"
+            );
+        }
+    }
+
+    #[test]
+    fn aligned_boids_share_a_heading() {
+        let boids = place(Formation::Aligned, 20);
+        assert!(boids.iter().all(|(_, vel)| *vel == boids[0].1));
+    }
+}
@@ -0,0 +1,160 @@
+use bevy::input::gamepad::{
+    Gamepad, GamepadAxis, GamepadAxisType, GamepadButton, GamepadButtonType, Gamepads,
+};
+use bevy::prelude::{
+    Assets, Axis, Color, Commands, Component, Local, Mesh, Query, Res, ResMut, Transform, Vec2,
+    Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use bevy::time::Time;
+
+use crate::debug::circle;
+use crate::input::{Camera, CameraControls};
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::replay::{SimEvent, SimEvents};
+use crate::State;
+
+/// Pixels the aim of the attractor has to move before it's sent, so holding the stick doesn't
+/// record an event every frame.
+const ATTRACTOR_STEP: f32 = 4.0;
+
+/// Marks the entity the attractor is drawn through.
+#[derive(Component)]
+pub struct AttractorMesh;
+
+pub fn spawn_attractor_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the boids
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, 0.6))
+        .insert(AttractorMesh);
+}
+
+/// Draws a crosshair where the attractor is placed.
+pub fn draw_attractor(
+    mut attractor_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<AttractorMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    state: Res<State>,
+    mut drawn: Local<Option<Vec2>>,
+    mut lines: Local<LineMesh>,
+) {
+    let Ok((handle, mut visibility)) = attractor_mesh.get_single_mut() else {
+        return;
+    };
+
+    if *drawn == state.attractor {
+        return;
+    }
+
+    *drawn = state.attractor;
+
+    lines.clear();
+    if let Some(pos) = state.attractor {
+        let color = Color::rgb(1.0, 0.6, 0.1);
+        circle(&mut lines, pos, 1.5, 0.25, color);
+        for dir in [Vec2::X, Vec2::Y] {
+            lines.segment(pos - dir * 2.5, pos + dir * 2.5, 0.25, color, color);
+        }
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
+
+fn stick(
+    axes: &Axis<GamepadAxis>,
+    gamepad: Gamepad,
+    x: GamepadAxisType,
+    y: GamepadAxisType,
+) -> Vec2 {
+    Vec2::new(
+        axes.get(GamepadAxis(gamepad, x)).unwrap_or(0.0),
+        axes.get(GamepadAxis(gamepad, y)).unwrap_or(0.0),
+    )
+}
+
+/// Analog controls for when only a gamepad is available. The left stick pans, the triggers zoom
+/// and the right stick moves the attractor. The buttons go through the bindings, by default A
+/// plays and pauses, the right bumper steps a single tick, Y spawns boids and X removes the
+/// attractor.
+pub fn handle_gamepad(
+    gamepads: Res<Gamepads>,
+    button_axes: Res<Axis<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    cameras: Query<&Transform, With<Camera>>,
+    timer: Res<Time>,
+    mut controls: ResMut<CameraControls>,
+    state: Res<State>,
+    mut events: ResMut<SimEvents>,
+    mut aim: Local<Option<Vec2>>,
+    mut sent: Local<Option<Vec2>>,
+) {
+    let Ok(transform) = cameras.get_single() else {
+        return;
+    };
+
+    let seconds = timer.delta_seconds();
+    let mut moved_attractor = false;
+    for &gamepad in gamepads.iter() {
+        let pan = stick(
+            &axes,
+            gamepad,
+            GamepadAxisType::LeftStickX,
+            GamepadAxisType::LeftStickY,
+        );
+        if pan != Vec2::ZERO {
+            controls.pan(transform, pan, seconds);
+        }
+
+        let trigger = |button| {
+            button_axes
+                .get(GamepadButton(gamepad, button))
+                .unwrap_or(0.0)
+        };
+        let zoom =
+            trigger(GamepadButtonType::LeftTrigger2) - trigger(GamepadButtonType::RightTrigger2);
+        if zoom != 0.0 {
+            let rate = 3.0 * controls.zoom_sensitivity * seconds;
+            controls.zoom(transform, (zoom * rate).exp(), None);
+        }
+
+        // The attractor moves as fast as the camera pans, starting from the center of the screen
+        let push = stick(
+            &axes,
+            gamepad,
+            GamepadAxisType::RightStickX,
+            GamepadAxisType::RightStickY,
+        );
+        if push != Vec2::ZERO {
+            let from = aim
+                .or(state.attractor)
+                .unwrap_or_else(|| transform.translation.truncate());
+            *aim = Some(from + push * controls.pan_speed * seconds * transform.scale.x);
+            moved_attractor = true;
+        }
+    }
+
+    // Only sending the aim once it moved far enough on screen, and wherever it ended up once the
+    // stick is let go
+    if let Some(to) = *aim {
+        let far_enough = sent.map_or(true, |it| {
+            it.distance(to) >= ATTRACTOR_STEP * transform.scale.x
+        });
+        if far_enough || (!moved_attractor && *sent != Some(to)) {
+            *sent = Some(to);
+            events.push(SimEvent::MoveAttractor(Some(to.to_array())));
+        }
+    }
+
+    // The event moving the attractor may not be applied before the next frame, so the stick
+    // keeps moving it from where it was aimed until it's let go
+    if !moved_attractor {
+        *aim = None;
+        *sent = None;
+    }
+}
@@ -0,0 +1,118 @@
+//! Points of interest like roosts or food that attract the boids around them.
+
+use bevy::math::Vec2;
+use serde::{Deserialize, Serialize};
+
+/// Fraction of the radius within which boids use up a goal's capacity.
+pub const REACH: f32 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GoalKind {
+    Roost,
+    Food,
+    Target,
+}
+
+impl GoalKind {
+    pub const ALL: [Self; 3] = [Self::Roost, Self::Food, Self::Target];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Roost => "Roost",
+            Self::Food => "Food",
+            Self::Target => "Target",
+        }
+    }
+
+    /// Goal of this kind with the settings it's usually placed with.
+    pub fn preset(self) -> Goal {
+        let (radius, strength, falloff, capacity) = match self {
+            Self::Roost => (60.0, 0.01, Falloff::Linear, None),
+            Self::Food => (25.0, 0.02, Falloff::Quadratic, Some(600.0)),
+            Self::Target => (100.0, 0.005, Falloff::Constant, None),
+        };
+
+        Goal {
+            kind: self,
+            position: [0.0, 0.0],
+            radius,
+            strength,
+            falloff,
+            capacity,
+        }
+    }
+}
+
+/// How the pull of a goal weakens towards the edge of its radius.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Falloff {
+    Constant,
+    Linear,
+    Quadratic,
+}
+
+impl Falloff {
+    pub const ALL: [Self; 3] = [Self::Constant, Self::Linear, Self::Quadratic];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Constant => "Constant",
+            Self::Linear => "Linear",
+            Self::Quadratic => "Quadratic",
+        }
+    }
+
+    /// Weight of the pull at `t` between the center at 0 and the edge at 1.
+    fn weight(self, t: f32) -> f32 {
+        match self {
+            Self::Constant => 1.0,
+            Self::Linear => 1.0 - t,
+            Self::Quadratic => (1.0 - t) * (1.0 - t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Goal {
+    pub kind: GoalKind,
+    pub position: [f32; 2],
+    /// Distance boids are attracted from
+    pub radius: f32,
+    pub strength: f32,
+    pub falloff: Falloff,
+    /// Boid ticks left before the goal is used up, `None` for goals that never run out
+    pub capacity: Option<f32>,
+}
+
+impl Goal {
+    pub fn is_depleted(&self) -> bool {
+        self.capacity.map_or(false, |it| it <= 0.0)
+    }
+
+    /// Velocity change of a boid at `pos` towards the goal.
+    pub fn pull(&self, pos: Vec2) -> Vec2 {
+        if self.is_depleted() {
+            return Vec2::ZERO;
+        }
+
+        let offset = Vec2::from(self.position) - pos;
+        let distance = offset.length();
+        if distance >= self.radius || distance == 0.0 {
+            return Vec2::ZERO;
+        }
+
+        offset / distance * self.strength * self.falloff.weight(distance / self.radius)
+    }
+
+    /// Whether a boid at `pos` is close enough to use the goal up.
+    pub fn reaches(&self, pos: Vec2) -> bool {
+        Vec2::from(self.position).distance(pos) < self.radius * REACH
+    }
+
+    /// Uses up one tick of capacity for each of `count` boids within reach.
+    pub fn consume(&mut self, count: usize) {
+        if let Some(capacity) = self.capacity.as_mut() {
+            *capacity = (*capacity - count as f32).max(0.0);
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Entity, Local, Mesh, Query, Res, ResMut, Transform, Vec2,
+    Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use bevy_egui::{egui, EguiContext};
+use boids::goal::{Falloff, Goal, GoalKind, REACH};
+
+use crate::debug::circle;
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::replay::{SimEvent, SimEvents};
+use crate::{egui_color, Boid, State};
+
+pub fn goal_color(kind: GoalKind) -> Color {
+    match kind {
+        GoalKind::Roost => Color::rgb(0.7, 0.5, 1.0),
+        GoalKind::Food => Color::rgb(1.0, 0.85, 0.3),
+        GoalKind::Target => Color::rgb(1.0, 0.4, 0.4),
+    }
+}
+
+/// Uses up the goals by the boids within their reach after a tick.
+pub fn consume_goals(goals: &mut [Goal], boids: &[(Entity, Boid, Transform)]) {
+    for goal in goals.iter_mut().filter(|it| it.capacity.is_some()) {
+        let count = boids
+            .iter()
+            .filter(|(_, _, transform)| goal.reaches(transform.translation.truncate()))
+            .count();
+        goal.consume(count);
+    }
+}
+
+/// Settings of the goal placed by the brush. Picking another kind starts over from its preset.
+pub fn goal_ui(ui: &mut egui::Ui, goal: &mut Goal) {
+    ui.horizontal(|ui| {
+        ui.label("Goal");
+        let mut kind = goal.kind;
+        egui::ComboBox::from_id_source("goal_kind")
+            .selected_text(kind.name())
+            .show_ui(ui, |ui| {
+                for it in GoalKind::ALL {
+                    ui.selectable_value(&mut kind, it, it.name());
+                }
+            });
+
+        if kind != goal.kind {
+            *goal = kind.preset();
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Goal Radius");
+        ui.add(egui::DragValue::new(&mut goal.radius).clamp_range(1.0..=500.0));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Goal Strength");
+        ui.add(
+            egui::DragValue::new(&mut goal.strength)
+                .speed(0.001)
+                .clamp_range(0.0..=0.5),
+        );
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Falloff");
+        egui::ComboBox::from_id_source("goal_falloff")
+            .selected_text(goal.falloff.name())
+            .show_ui(ui, |ui| {
+                for falloff in Falloff::ALL {
+                    ui.selectable_value(&mut goal.falloff, falloff, falloff.name());
+                }
+            });
+    });
+
+    ui.horizontal(|ui| {
+        let mut limited = goal.capacity.is_some();
+        ui.checkbox(&mut limited, "Capacity");
+        if limited != goal.capacity.is_some() {
+            goal.capacity = limited.then_some(600.0);
+        }
+
+        if let Some(capacity) = goal.capacity.as_mut() {
+            ui.add(egui::DragValue::new(capacity).clamp_range(1.0..=100_000.0));
+        }
+    });
+}
+
+/// Window listing the placed goals, shown while there are any.
+pub fn goals_gui(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut events: ResMut<SimEvents>,
+    state: Res<State>,
+) {
+    if state.goals.is_empty() {
+        return;
+    }
+
+    egui::Window::new("Goals")
+        .default_open(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            for (i, goal) in state.goals.iter().enumerate() {
+                ui.horizontal(|ui| {
+                    let [x, y] = goal.position;
+                    let label = format!("{} at {x:.0}, {y:.0}", goal.kind.name());
+                    ui.colored_label(egui_color(goal_color(goal.kind)), label);
+
+                    if let Some(capacity) = goal.capacity {
+                        ui.label(format!("{capacity:.0} left"));
+                    }
+
+                    if ui.small_button("✖").clicked() {
+                        events.push(SimEvent::RemoveGoal(i));
+                    }
+                });
+            }
+        });
+}
+
+/// Marks the entity the goals are drawn through.
+#[derive(Component)]
+pub struct GoalMesh;
+
+pub fn spawn_goal_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the trails and under the boids
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, -0.2))
+        .insert(GoalMesh);
+}
+
+/// Outlines the radius every goal attracts from and the reach boids use it up within, depleted
+/// goals fade out.
+pub fn draw_goals(
+    mut goal_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<GoalMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    state: Res<State>,
+    mut lines: Local<LineMesh>,
+) {
+    if !state.is_changed() {
+        return;
+    }
+
+    let Ok((handle, mut visibility)) = goal_mesh.get_single_mut() else {
+        return;
+    };
+
+    lines.clear();
+    for goal in state.goals.iter() {
+        let pos = Vec2::from(goal.position);
+        let alpha = if goal.is_depleted() { 0.2 } else { 0.7 };
+        let mut color = goal_color(goal.kind);
+        color.set_a(alpha);
+        let width = goal.radius * 0.01;
+
+        circle(&mut lines, pos, goal.radius, width, color);
+        if goal.capacity.is_some() {
+            circle(&mut lines, pos, goal.radius * REACH, width, color);
+        }
+
+        let arm = Vec2::splat(goal.radius * 0.05);
+        lines.segment(pos - arm, pos + arm, width * 2.0, color, color);
+        let arm = Vec2::new(arm.x, -arm.y);
+        lines.segment(pos - arm, pos + arm, width * 2.0, color, color);
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
@@ -0,0 +1,285 @@
+use bevy::math::Vec2;
+
+/// Grids with more cells than this per boid get coarser cells instead, so that a few strays far
+/// away from the flock can't blow up the cell array.
+const MAX_CELLS_PER_ENTRY: usize = 4;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GridEntry {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    /// Boids only flock with their own species
+    pub species: u8,
+    /// Leaders count more towards the averages of the boids that see them
+    pub leader: bool,
+}
+
+/// Cells of a uniform grid covering a set of positions.
+#[derive(Debug, Clone, Copy)]
+pub struct CellLayout {
+    origin: Vec2,
+    cell_size: f32,
+    cols: usize,
+    rows: usize,
+}
+
+/// Cells overlapped by a radius query, both corners inclusive.
+#[derive(Debug, Clone, Copy)]
+pub struct CellRange {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl CellLayout {
+    /// Fits a grid around `positions` with cells of at least `cell_size`. Returns `None` when
+    /// there are no positions.
+    pub fn new(positions: impl Iterator<Item = Vec2>, cell_size: f32) -> Option<Self> {
+        let (min, max, len) = positions.fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN), 0),
+            |(min, max, len), it| (min.min(it), max.max(it), len + 1),
+        );
+
+        if len == 0 {
+            return None;
+        }
+
+        let size = max - min;
+        let max_cells = len * MAX_CELLS_PER_ENTRY + 64;
+        let mut cell_size = cell_size.max(0.01);
+        let (cols, rows) = loop {
+            let (cols, rows) = dimensions(size, cell_size);
+            if cols * rows <= max_cells {
+                break (cols, rows);
+            }
+
+            cell_size *= 2.0;
+        };
+
+        Some(Self {
+            origin: min,
+            cell_size,
+            cols,
+            rows,
+        })
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn cell_count(&self) -> usize {
+        self.cols * self.rows
+    }
+
+    pub fn cell_of(&self, pos: Vec2) -> usize {
+        let cell = ((pos - self.origin) / self.cell_size).floor();
+        let x = (cell.x.max(0.0) as usize).min(self.cols - 1);
+        let y = (cell.y.max(0.0) as usize).min(self.rows - 1);
+        y * self.cols + x
+    }
+
+    /// Cells that can hold positions within `radius` of `pos`, `None` if it's outside the grid.
+    pub fn cells_within(&self, pos: Vec2, radius: f32) -> Option<CellRange> {
+        let min = ((pos - radius - self.origin) / self.cell_size).floor();
+        let max = ((pos + radius - self.origin) / self.cell_size).floor();
+        if max.x < 0.0 || max.y < 0.0 || min.x >= self.cols as f32 || min.y >= self.rows as f32 {
+            return None;
+        }
+
+        Some(CellRange {
+            x0: min.x.max(0.0) as usize,
+            y0: min.y.max(0.0) as usize,
+            x1: (max.x as usize).min(self.cols - 1),
+            y1: (max.y as usize).min(self.rows - 1),
+        })
+    }
+
+    /// Stable counting sort by cell. Returns the offset of the first item of every cell followed
+    /// by the total, and the index of the item that belongs at every sorted position.
+    pub fn sort(&self, positions: impl Iterator<Item = Vec2>) -> (Vec<u32>, Vec<u32>) {
+        let cells = positions.map(|it| self.cell_of(it)).collect::<Vec<_>>();
+
+        let mut cell_starts = vec![0; self.cell_count() + 1];
+        for cell in cells.iter() {
+            cell_starts[cell + 1] += 1;
+        }
+
+        for i in 1..cell_starts.len() {
+            cell_starts[i] += cell_starts[i - 1];
+        }
+
+        let mut order = vec![0; cells.len()];
+        let mut next = cell_starts.clone();
+        for (i, cell) in cells.into_iter().enumerate() {
+            order[next[cell] as usize] = i as u32;
+            next[cell] += 1;
+        }
+
+        (cell_starts, order)
+    }
+}
+
+/// Uniform grid spatial hash rebuilt every tick. Entries are sorted by cell into one contiguous
+/// array and carry their velocity, so a radius query only reads the few cells it overlaps and
+/// never has to go back to the ECS.
+pub struct SpatialGrid {
+    layout: Option<CellLayout>,
+    /// Offset of the first entry of every cell, followed by the total number of entries
+    cell_starts: Vec<u32>,
+    entries: Vec<GridEntry>,
+}
+
+impl SpatialGrid {
+    pub fn new(entries: &[GridEntry], cell_size: f32) -> Self {
+        let Some(layout) = CellLayout::new(entries.iter().map(|it| it.pos), cell_size) else {
+            return Self {
+                layout: None,
+                cell_starts: vec![0],
+                entries: Vec::new(),
+            };
+        };
+
+        let (cell_starts, order) = layout.sort(entries.iter().map(|it| it.pos));
+        Self {
+            layout: Some(layout),
+            cell_starts,
+            entries: order.into_iter().map(|i| entries[i as usize]).collect(),
+        }
+    }
+
+    /// Every entry, sorted by cell.
+    pub fn entries(&self) -> &[GridEntry] {
+        &self.entries
+    }
+
+    /// Calls `f` for every entry within `radius` of `pos`, including an entry at `pos` itself.
+    pub fn for_each_within(&self, pos: Vec2, radius: f32, mut f: impl FnMut(&GridEntry)) {
+        let Some(layout) = self.layout else {
+            return;
+        };
+
+        let Some(range) = layout.cells_within(pos, radius) else {
+            return;
+        };
+
+        let radius_sq = radius * radius;
+        for y in range.y0..=range.y1 {
+            let row = y * layout.cols();
+            let start = self.cell_starts[row + range.x0] as usize;
+            let end = self.cell_starts[row + range.x1 + 1] as usize;
+
+            // The cells of a row are adjacent in the entry array, so they're read as one slice
+            for entry in self.entries[start..end].iter() {
+                if entry.pos.distance_squared(pos) <= radius_sq {
+                    f(entry);
+                }
+            }
+        }
+    }
+}
+
+fn dimensions(size: Vec2, cell_size: f32) -> (usize, usize) {
+    (
+        (size.x / cell_size) as usize + 1,
+        (size.y / cell_size) as usize + 1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{GridEntry, SpatialGrid};
+
+    /// Entries at `positions`, tagged with their index through the velocity.
+    fn entries(positions: &[Vec2]) -> Vec<GridEntry> {
+        positions
+            .iter()
+            .enumerate()
+            .map(|(i, pos)| GridEntry {
+                pos: *pos,
+                vel: Vec2::new(i as f32, 0.0),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    /// Checks the grid finds exactly the entries a brute force search does around every query.
+    fn assert_matches_brute_force(entries: &[GridEntry], grid: &SpatialGrid, queries: &[Vec2]) {
+        for radius in [0.5, 3.0, 10.0, 25.0] {
+            for pos in queries.iter().copied() {
+                let mut found = Vec::new();
+                grid.for_each_within(pos, radius, |it| found.push(it.vel.x as usize));
+                found.sort_unstable();
+
+                let expected = entries
+                    .iter()
+                    .filter(|it| it.pos.distance_squared(pos) <= radius * radius)
+                    .map(|it| it.vel.x as usize)
+                    .collect::<Vec<_>>();
+
+                assert_eq!(found, expected, "within {radius} of {pos}");
+            }
+        }
+    }
+
+    #[test]
+    fn matches_brute_force_around_negative_coordinates() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut positions = (0..500)
+            .map(|_| Vec2::new(rng.gen_range(-80.0..-5.0), rng.gen_range(-40.0..30.0)))
+            .collect::<Vec<_>>();
+        // Entries right on the cell edges and corners of the grid
+        positions.extend((-8..=0).map(|i| Vec2::splat(i as f32 * 10.0)));
+
+        let entries = entries(&positions);
+        let grid = SpatialGrid::new(&entries, 10.0);
+
+        let mut queries = positions.iter().step_by(7).copied().collect::<Vec<_>>();
+        // Queries outside of the grid and just past its edges
+        queries.extend([
+            Vec2::new(-200.0, 0.0),
+            Vec2::new(0.0, 100.0),
+            Vec2::new(-80.5, -40.5),
+            Vec2::new(-4.0, 31.0),
+        ]);
+
+        assert_matches_brute_force(&entries, &grid, &queries);
+    }
+
+    #[test]
+    fn matches_brute_force_after_coarsening() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut positions = (0..50)
+            .map(|_| Vec2::new(rng.gen_range(-20.0..20.0), rng.gen_range(-20.0..20.0)))
+            .collect::<Vec<_>>();
+        // A few strays far away stretch the grid way past the cells the entries allow for
+        positions.extend([
+            Vec2::new(-5000.0, -3000.0),
+            Vec2::new(4000.0, 2500.0),
+            Vec2::new(-4990.0, 2495.0),
+        ]);
+
+        let entries = entries(&positions);
+        let grid = SpatialGrid::new(&entries, 5.0);
+        assert!(
+            grid.layout.unwrap().cell_size > 5.0,
+            "the grid wasn't coarsened"
+        );
+
+        let mut queries = positions.clone();
+        queries.extend([Vec2::new(-4995.0, 2500.0), Vec2::new(0.0, 0.0)]);
+
+        assert_matches_brute_force(&entries, &grid, &queries);
+    }
+
+    #[test]
+    fn finds_nothing_without_entries() {
+        let grid = SpatialGrid::new(&[], 10.0);
+        grid.for_each_within(Vec2::ZERO, 10.0, |_| panic!("the grid is empty"));
+    }
+}
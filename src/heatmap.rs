@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+
+use bevy::math::IVec2;
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Local, Mesh, Query, Res, ResMut, Vec2, Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use serde::{Deserialize, Serialize};
+
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::{LastGrid, Options, State, TICK_RATE};
+
+/// Colors the heatmap goes through from empty to the densest cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HeatmapRamp {
+    Heat,
+    Viridis,
+    Grayscale,
+}
+
+impl HeatmapRamp {
+    pub const ALL: [Self; 3] = [Self::Heat, Self::Viridis, Self::Grayscale];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Heat => "Heat",
+            Self::Viridis => "Viridis",
+            Self::Grayscale => "Grayscale",
+        }
+    }
+
+    fn stops(self) -> &'static [[f32; 3]] {
+        match self {
+            Self::Heat => &[
+                [0.0, 0.0, 0.5],
+                [0.8, 0.0, 0.0],
+                [1.0, 0.6, 0.0],
+                [1.0, 1.0, 0.8],
+            ],
+            Self::Viridis => &[
+                [0.27, 0.0, 0.33],
+                [0.23, 0.32, 0.55],
+                [0.13, 0.57, 0.55],
+                [0.37, 0.79, 0.38],
+                [0.99, 0.91, 0.14],
+            ],
+            Self::Grayscale => &[[0.1, 0.1, 0.1], [1.0, 1.0, 1.0]],
+        }
+    }
+
+    /// Color at `t` between 0 and 1, denser cells are also more opaque.
+    fn sample(self, t: f32) -> Color {
+        let stops = self.stops();
+        let scaled = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let i = (scaled as usize).min(stops.len() - 2);
+        let f = scaled - i as f32;
+        let [r, g, b] = [0, 1, 2].map(|c| stops[i][c] + (stops[i + 1][c] - stops[i][c]) * f);
+        Color::rgba(r, g, b, 0.15 + 0.6 * t)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct HeatmapCell {
+    density: f32,
+    velocity: Vec2,
+}
+
+/// Marks the entity the heatmap is drawn through.
+#[derive(Component)]
+pub struct HeatmapMesh;
+
+/// Density of boids per cell, accumulated over time and fading with the configured half-life.
+#[derive(Default)]
+pub struct Heatmap {
+    cell_size: f32,
+    cells: HashMap<IVec2, HeatmapCell>,
+    /// Tick the boids were last counted on
+    tick: Option<u64>,
+    lines: LineMesh,
+}
+
+pub fn spawn_heatmap_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn under the trails and boids
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, -1.0))
+        .insert(HeatmapMesh);
+}
+
+/// Adds the boids of the last tick to the heatmap for every tick that ran since the last update
+/// and redraws it.
+///
+/// The boids are read from the grid of the last tick, which already holds their positions and
+/// velocities sorted by cell.
+pub fn update_heatmap(
+    last_grid: Res<LastGrid>,
+    mut heatmap_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<HeatmapMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    options: Res<Options>,
+    state: Res<State>,
+    mut heatmap: Local<Heatmap>,
+) {
+    let Ok((handle, mut visibility)) = heatmap_mesh.get_single_mut() else {
+        return;
+    };
+
+    // Starting over when the cells change size or the heatmap is turned off
+    if !options.heatmap || heatmap.cell_size != options.heatmap_cell_size {
+        heatmap.cells.clear();
+        heatmap.cell_size = options.heatmap_cell_size;
+        heatmap.tick = None;
+    }
+
+    if !options.heatmap {
+        if visibility.is_visible {
+            visibility.is_visible = false;
+        }
+
+        return;
+    }
+
+    // Nothing is added while paused, a rewind counts the boids again from the next tick
+    let ticks = match heatmap.tick {
+        Some(tick) => state.tick.saturating_sub(tick),
+        None => 1,
+    };
+
+    heatmap.tick = Some(state.tick);
+    let cell_size = heatmap.cell_size;
+    if ticks > 0 {
+        // Older visits fade out so the map shows where the flock has been lately
+        let decay = 0.5_f32.powf(ticks as f32 / (TICK_RATE as f32 * options.heatmap_half_life));
+        heatmap.cells.retain(|_, cell| {
+            cell.density *= decay;
+            cell.velocity *= decay;
+            cell.density > 0.01
+        });
+
+        let weight = ticks as f32;
+        let mut add = |cell: IVec2, sum: HeatmapCell| {
+            let cell = heatmap.cells.entry(cell).or_default();
+            cell.density += sum.density * weight;
+            cell.velocity += sum.velocity * weight;
+        };
+
+        // Neighboring entries of the grid are close together and mostly share a heatmap cell, so
+        // runs of them are summed up before they're added
+        let mut run: Option<(IVec2, HeatmapCell)> = None;
+        let entries = last_grid.0.as_ref().map_or(&[][..], |it| it.entries());
+        for entry in entries {
+            let cell = (entry.pos / cell_size).floor().as_ivec2();
+            match &mut run {
+                Some((run_cell, sum)) if *run_cell == cell => {
+                    sum.density += 1.0;
+                    sum.velocity += entry.vel;
+                }
+                _ => {
+                    let sum = HeatmapCell {
+                        density: 1.0,
+                        velocity: entry.vel,
+                    };
+                    if let Some((run_cell, sum)) = run.replace((cell, sum)) {
+                        add(run_cell, sum);
+                    }
+                }
+            }
+        }
+
+        if let Some((cell, sum)) = run {
+            add(cell, sum);
+        }
+    }
+
+    let max_density = heatmap
+        .cells
+        .values()
+        .fold(0.0_f32, |max, it| max.max(it.density));
+
+    let Heatmap { cells, lines, .. } = &mut *heatmap;
+    lines.clear();
+    for (cell, value) in cells.iter() {
+        let min = cell.as_vec2() * cell_size;
+        let color = options.heatmap_ramp.sample(value.density / max_density);
+        lines.rect(min, min + Vec2::splat(cell_size), color);
+    }
+
+    // Arrows on top of the cells pointing along the mean velocity
+    if options.heatmap_velocity {
+        for (cell, value) in cells.iter() {
+            let Some(dir) = value.velocity.try_normalize() else { continue; };
+
+            let center = (cell.as_vec2() + 0.5) * cell_size;
+            let offset = dir * cell_size * 0.4;
+            lines.arrow(
+                center - offset,
+                center + offset,
+                cell_size * 0.06,
+                Color::rgba(1.0, 1.0, 1.0, 0.8),
+            );
+        }
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
@@ -0,0 +1,299 @@
+use std::collections::VecDeque;
+use std::mem::size_of;
+use std::sync::Arc;
+
+use bevy::prelude::{Entity, Query, ResMut, Transform, Vec2};
+
+use boids::goal::Goal;
+
+use crate::{Boid, State};
+
+/// Ticks between two history frames, the timeline rewinds in steps of this many ticks.
+pub const HISTORY_INTERVAL: u64 = 4;
+
+/// Frames are dropped from the front once the history takes up more memory than this.
+const MAX_HISTORY_BYTES: usize = 256 * 1024 * 1024;
+
+/// Copy of every boid taken right after a simulation tick, as structure of arrays holding only
+/// what changes while the simulation runs.
+pub struct HistoryFrame {
+    pub tick: u64,
+    pub border_center: Vec2,
+    pub migration_distance: f32,
+    /// Shared with the previous frame as long as the goals don't change
+    pub goals: Arc<Vec<Goal>>,
+    pub entities: Vec<Entity>,
+    pub pos: Vec<Vec2>,
+    pub vel: Vec<Vec2>,
+    pub flock_size: Vec<u32>,
+    pub leader: Vec<bool>,
+}
+
+impl HistoryFrame {
+    pub fn new(state: &State, boids: &[(Entity, Boid, Transform)], last: Option<&Self>) -> Self {
+        let goals = match last {
+            Some(last) if *last.goals == state.goals => last.goals.clone(),
+            _ => Arc::new(state.goals.clone()),
+        };
+
+        Self {
+            tick: state.tick,
+            border_center: state.border_center,
+            migration_distance: state.migration_distance,
+            goals,
+            entities: boids.iter().map(|it| it.0).collect(),
+            pos: boids.iter().map(|it| it.2.translation.truncate()).collect(),
+            vel: boids.iter().map(|it| Vec2::new(it.1.vx, it.1.vy)).collect(),
+            flock_size: boids.iter().map(|it| it.1.flock_size).collect(),
+            leader: boids.iter().map(|it| it.1.leader).collect(),
+        }
+    }
+
+    /// Memory taken up by the boids of the frame.
+    fn bytes(&self) -> usize {
+        self.entities.len()
+            * (size_of::<Entity>() + 2 * size_of::<Vec2>() + size_of::<u32>() + size_of::<bool>())
+    }
+}
+
+/// Ring buffer holding the most recent simulation ticks so they can be scrubbed through with the
+/// timeline slider.
+#[derive(Default)]
+pub struct History {
+    frames: VecDeque<HistoryFrame>,
+    /// Memory taken up by all frames
+    bytes: usize,
+    /// Frame currently shown while scrubbing, `None` when following the live simulation
+    cursor: Option<usize>,
+    dirty: bool,
+}
+
+impl History {
+    pub fn push(&mut self, frame: HistoryFrame, capacity: usize) {
+        self.bytes += frame.bytes();
+        self.frames.push_back(frame);
+        while self.frames.len() > capacity.max(1)
+            || (self.bytes > MAX_HISTORY_BYTES && self.frames.len() > 1)
+        {
+            if let Some(dropped) = self.frames.pop_front() {
+                self.bytes -= dropped.bytes();
+            }
+        }
+    }
+
+    pub fn last(&self) -> Option<&HistoryFrame> {
+        self.frames.back()
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.bytes = 0;
+        self.cursor = None;
+        self.dirty = false;
+    }
+
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    pub fn scrub_to(&mut self, index: usize) {
+        if self.frames.is_empty() {
+            return;
+        }
+
+        self.cursor = Some(index.min(self.frames.len() - 1));
+        self.dirty = true;
+    }
+
+    /// Stops scrubbing and drops every frame after the one being viewed, so the simulation
+    /// continues from that point. Returns the frame that is resumed from, if any.
+    pub fn resume(&mut self) -> Option<&HistoryFrame> {
+        let index = self.cursor.take()?;
+        for dropped in self.frames.drain(index + 1..) {
+            self.bytes -= dropped.bytes();
+        }
+
+        self.dirty = false;
+        self.frames.back()
+    }
+}
+
+/// Writes the frame selected with the timeline slider back onto the boids.
+pub fn restore_history_frame(
+    mut history: ResMut<History>,
+    mut query: Query<(&mut Boid, &mut Transform)>,
+    mut state: ResMut<State>,
+) {
+    if !history.dirty {
+        return;
+    }
+
+    history.dirty = false;
+
+    let Some(frame) = history.cursor.and_then(|it| history.frames.get(it)) else {
+        return;
+    };
+
+    // Boids that were despawned since the frame was taken can't be brought back
+    for (i, entity) in frame.entities.iter().enumerate() {
+        let Ok((mut boid, mut transform)) = query.get_mut(*entity) else { continue; };
+
+        boid.vx = frame.vel[i].x;
+        boid.vy = frame.vel[i].y;
+        boid.flock_size = frame.flock_size[i];
+        boid.leader = frame.leader[i];
+        transform.translation.x = frame.pos[i].x;
+        transform.translation.y = frame.pos[i].y;
+    }
+
+    state.tick = frame.tick;
+    state.border_center = frame.border_center;
+    state.migration_distance = frame.migration_distance;
+    state.goals = frame.goals.to_vec();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bevy::ecs::schedule::{Stage, SystemStage};
+    use bevy::prelude::{Entity, Transform, Vec2, Vec3, World};
+    use boids::goal::GoalKind;
+
+    use super::{restore_history_frame, History, HistoryFrame};
+    use crate::{Boid, State};
+
+    fn boids(count: u32, tick: u64) -> Vec<(Entity, Boid, Transform)> {
+        (0..count)
+            .map(|i| {
+                let boid = Boid {
+                    id: i,
+                    vx: tick as f32,
+                    ..Default::default()
+                };
+                let transform = Transform::from_xyz(i as f32, tick as f32, 0.0);
+                (Entity::from_raw(i), boid, transform)
+            })
+            .collect()
+    }
+
+    /// History with a frame of `count` boids on every tick from 1 to `ticks`.
+    fn filled_history(ticks: u64, count: u32, capacity: usize) -> (History, State) {
+        let mut history = History::default();
+        let mut state = State::default();
+        for tick in 1..=ticks {
+            state.tick = tick;
+            let frame = HistoryFrame::new(&state, &boids(count, tick), history.last());
+            history.push(frame, capacity);
+        }
+
+        (history, state)
+    }
+
+    fn ticks(history: &History) -> Vec<u64> {
+        history.frames.iter().map(|it| it.tick).collect()
+    }
+
+    #[test]
+    fn drops_the_oldest_frames_past_the_capacity() {
+        let (history, _) = filled_history(10, 5, 3);
+        assert_eq!(ticks(&history), [8, 9, 10]);
+        assert_eq!(history.bytes(), 3 * history.frames[0].bytes());
+
+        // There's always room for the latest frame
+        let (history, _) = filled_history(4, 5, 0);
+        assert_eq!(ticks(&history), [4]);
+    }
+
+    #[test]
+    fn shares_the_goals_with_the_last_frame_until_they_change() {
+        let (mut history, mut state) = filled_history(2, 3, 10);
+        let first = history.frames[0].goals.clone();
+        assert!(Arc::ptr_eq(&first, &history.frames[1].goals));
+
+        state.tick = 3;
+        state.goals.push(GoalKind::Food.preset());
+        let frame = HistoryFrame::new(&state, &boids(3, 3), history.last());
+        assert!(!Arc::ptr_eq(&first, &frame.goals));
+        assert_eq!(*frame.goals, state.goals);
+        history.push(frame, 10);
+
+        state.tick = 4;
+        let frame = HistoryFrame::new(&state, &boids(3, 4), history.last());
+        assert!(Arc::ptr_eq(&history.frames[2].goals, &frame.goals));
+    }
+
+    #[test]
+    fn resuming_drops_the_frames_after_the_cursor() {
+        let (mut history, _) = filled_history(5, 4, 10);
+        assert!(history.resume().is_none(), "not scrubbing");
+
+        history.scrub_to(1);
+        assert_eq!(history.cursor(), Some(1));
+        assert_eq!(history.resume().map(|it| it.tick), Some(2));
+        assert_eq!(ticks(&history), [1, 2]);
+        assert_eq!(history.bytes(), 2 * history.frames[0].bytes());
+        assert_eq!(history.cursor(), None);
+        assert!(history.resume().is_none(), "resumed already");
+
+        // Scrubbing past the end stays on the last frame
+        history.scrub_to(100);
+        assert_eq!(history.resume().map(|it| it.tick), Some(2));
+        assert_eq!(history.len(), 2);
+    }
+
+    #[test]
+    fn restores_the_frame_under_the_cursor() {
+        let mut world = World::new();
+        let entities = (0..3)
+            .map(|_| {
+                world
+                    .spawn()
+                    .insert_bundle((Boid::default(), Transform::default()))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+
+        let mut state = State::default();
+        let mut history = History::default();
+        for tick in 1..=3 {
+            state.tick = tick;
+            state.border_center = Vec2::splat(tick as f32);
+            let mut frame_boids = boids(3, tick);
+            for (boid, entity) in frame_boids.iter_mut().zip(&entities) {
+                boid.0 = *entity;
+            }
+
+            history.push(HistoryFrame::new(&state, &frame_boids, history.last()), 10);
+        }
+
+        history.scrub_to(1);
+        world.insert_resource(history);
+        world.insert_resource(state);
+        SystemStage::single_threaded()
+            .with_system(restore_history_frame)
+            .run(&mut world);
+
+        let state = world.resource::<State>();
+        assert_eq!(state.tick, 2);
+        assert_eq!(state.border_center, Vec2::splat(2.0));
+        for (i, entity) in entities.iter().enumerate() {
+            let boid = world.get::<Boid>(*entity).unwrap();
+            let transform = world.get::<Transform>(*entity).unwrap();
+            assert_eq!(boid.vx, 2.0);
+            assert_eq!(transform.translation, Vec3::new(i as f32, 2.0, 0.0));
+        }
+    }
+}
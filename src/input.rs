@@ -1,59 +1,161 @@
 use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::input::touch::Touches;
 use bevy::prelude::{
-    App, Camera2d, Component, Deref, DerefMut, EventReader, Input, KeyCode, MouseButton, Plugin,
-    Query, Res, ResMut, Transform, Vec2, With,
+    App, Camera2d, Component, Deref, DerefMut, EventReader, Plugin, Query, Res, ResMut, Transform,
+    Vec2, With, Without,
 };
 use bevy::time::Time;
-use bevy::window::{CursorMoved, Windows};
+use bevy::window::{CursorMoved, Window, Windows};
+use bevy_egui::{EguiContext, EguiSettings};
 use num::clamp;
 
+use crate::bindings::{Action, Actions};
+use crate::inspect::Selection;
+use crate::{Boid, Options};
+
 #[derive(Component)]
 pub struct Camera;
 
-/// Simple script that handles panning with the keyboard.
-pub fn handle_keyboard_pan_and_zoom(
-    mut cameras: Query<&mut Transform, With<Camera>>,
-    timer: Res<Time>,
-    keyboard_input: Res<Input<KeyCode>>,
-) {
-    if keyboard_input.pressed(KeyCode::W) {
-        for mut transform in &mut cameras {
-            transform.translation.y += 1000.0 * timer.delta_seconds();
+/// What the camera keeps centered on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FollowTarget {
+    Off,
+    Selected,
+    /// The selected boid and the boids of its species it can see
+    Flock,
+    All,
+}
+
+impl FollowTarget {
+    pub const ALL: [Self; 4] = [Self::Off, Self::Selected, Self::Flock, Self::All];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "Off",
+            Self::Selected => "Selected Boid",
+            Self::Flock => "Selected Flock",
+            Self::All => "All Boids",
         }
     }
+}
 
-    if keyboard_input.pressed(KeyCode::A) {
-        for mut transform in &mut cameras {
-            transform.translation.x -= 1000.0 * timer.delta_seconds();
+/// Scales the camera can zoom between, in world units per pixel.
+const MIN_SCALE: f32 = 0.03;
+const MAX_SCALE: f32 = 10.0;
+
+/// How much room is left around the flock when framing it, relative to its size.
+const FIT_MARGIN: f32 = 1.2;
+
+/// Camera movement settings and the position and zoom the camera eases towards. Panning by hand
+/// turns following off.
+pub struct CameraControls {
+    pub follow: FollowTarget,
+    /// How quickly the camera catches up with its target, higher is snappier
+    pub smoothing: f32,
+    pub zoom_sensitivity: f32,
+    /// Keyboard panning speed in pixels per second, so it feels the same at every zoom level
+    pub pan_speed: f32,
+    /// Taken from the camera on the first input
+    target: Option<CameraTarget>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CameraTarget {
+    translation: Vec2,
+    scale: f32,
+}
+
+impl Default for CameraControls {
+    fn default() -> Self {
+        Self {
+            follow: FollowTarget::Off,
+            smoothing: 10.0,
+            zoom_sensitivity: 1.0,
+            pan_speed: 1000.0,
+            target: None,
         }
     }
+}
 
-    if keyboard_input.pressed(KeyCode::S) {
-        for mut transform in &mut cameras {
-            transform.translation.y -= 1000.0 * timer.delta_seconds();
-        }
+impl CameraControls {
+    fn target(&mut self, transform: &Transform) -> &mut CameraTarget {
+        self.target.get_or_insert(CameraTarget {
+            translation: transform.translation.truncate(),
+            scale: transform.scale.x,
+        })
     }
 
-    if keyboard_input.pressed(KeyCode::D) {
-        for mut transform in &mut cameras {
-            transform.translation.x += 1000.0 * timer.delta_seconds();
-        }
+    /// Pans `seconds` worth of movement in `direction`, which stops following.
+    pub fn pan(&mut self, transform: &Transform, direction: Vec2, seconds: f32) {
+        self.follow = FollowTarget::Off;
+        let distance = self.pan_speed * seconds;
+        let target = self.target(transform);
+        target.translation += direction * distance * target.scale;
     }
 
-    if keyboard_input.pressed(KeyCode::Q) {
-        for mut transform in &mut cameras {
-            let new_scale = transform.scale.x + (3.5 * timer.delta_seconds());
-            transform.scale.x = clamp(new_scale, 0.05, 10.0);
-            transform.scale.y = clamp(new_scale, 0.05, 10.0);
-        }
+    /// Moves the camera to `pos`, which stops following.
+    pub fn pan_to(&mut self, transform: &Transform, pos: Vec2) {
+        self.follow = FollowTarget::Off;
+        self.target(transform).translation = pos;
     }
 
-    if keyboard_input.pressed(KeyCode::E) {
-        for mut transform in &mut cameras {
-            let new_scale = transform.scale.x - (3.5 * timer.delta_seconds());
-            transform.scale.x = clamp(new_scale, 0.05, 10.0);
-            transform.scale.y = clamp(new_scale, 0.05, 10.0);
+    /// Multiplies the zoom by `factor`, keeping the world position `anchor` at the same spot on
+    /// screen, or the center when there's none. The anchor is where that spot is with the camera
+    /// at `transform`, which can still be easing towards the target.
+    pub fn zoom(&mut self, transform: &Transform, factor: f32, anchor: Option<Vec2>) {
+        let target = self.target(transform);
+        let scale = clamp(target.scale * factor, MIN_SCALE, MAX_SCALE);
+        if let Some(anchor) = anchor {
+            // Offset of the spot from the center of the screen, in pixels
+            let offset = (anchor - transform.translation.truncate()) / transform.scale.x;
+            let anchor = target.translation + offset * target.scale;
+            target.translation = anchor - offset * scale;
         }
+
+        target.scale = scale;
+    }
+}
+
+/// Simple script that handles panning and zooming with the keyboard.
+pub fn handle_keyboard_pan_and_zoom(
+    cameras: Query<&Transform, With<Camera>>,
+    timer: Res<Time>,
+    actions: Actions,
+    mut controls: ResMut<CameraControls>,
+) {
+    let Ok(transform) = cameras.get_single() else {
+        return;
+    };
+
+    let mut direction = Vec2::ZERO;
+    if actions.pressed(Action::PanUp) {
+        direction.y += 1.0;
+    }
+
+    if actions.pressed(Action::PanLeft) {
+        direction.x -= 1.0;
+    }
+
+    if actions.pressed(Action::PanDown) {
+        direction.y -= 1.0;
+    }
+
+    if actions.pressed(Action::PanRight) {
+        direction.x += 1.0;
+    }
+
+    if direction != Vec2::ZERO {
+        controls.pan(transform, direction, timer.delta_seconds());
+    }
+
+    // Zooming by a constant factor per second feels equally fast at every zoom level
+    let rate = 3.0 * controls.zoom_sensitivity * timer.delta_seconds();
+    if actions.pressed(Action::ZoomOut) {
+        controls.zoom(transform, rate.exp(), None);
+    }
+
+    if actions.pressed(Action::ZoomIn) {
+        controls.zoom(transform, (-rate).exp(), None);
     }
 }
 
@@ -62,30 +164,35 @@ pub struct CursorPanState {
     last_pos: Option<Vec2>,
 }
 
-/// Simple system that handles mouse panning and zooming. You can zoom with the
-/// scrolling wheel and pan by holding down left click on the mouse.
-///
-/// An improvement that could be made is zooming on the user's mouse cursor.
+/// Simple system that handles mouse panning and zooming. You can zoom towards the cursor with
+/// the scrolling wheel and pan by holding down right click, or whatever drag to pan is bound to.
 pub fn handle_mouse_pan_and_zoom(
     mut cameras: Query<&mut Transform, With<Camera>>,
-    mouse_btn_input: Res<Input<MouseButton>>,
+    actions: Actions,
     mut cursor_move_events: EventReader<CursorMoved>,
     mut mouse_wheel_events: EventReader<MouseWheel>,
     mut pan_state: ResMut<CursorPanState>,
-    timer: Res<Time>,
+    mut controls: ResMut<CameraControls>,
+    cursor: Res<CursorPosition>,
 ) {
-    if mouse_btn_input.pressed(MouseButton::Right) && !cursor_move_events.is_empty() {
+    let Ok(mut transform) = cameras.get_single_mut() else {
+        return;
+    };
+
+    if actions.pressed(Action::DragPan) && !cursor_move_events.is_empty() {
         let curr_pos = cursor_move_events.iter().last().map(|it| it.position);
 
         if let Some(curr_pos) = curr_pos {
             if let Some(last_pos) = &pan_state.last_pos {
                 let delta = curr_pos - *last_pos;
 
+                // Dragging moves the camera right away so the world sticks to the cursor
                 if delta != Vec2::ZERO {
-                    for mut transform in &mut cameras {
-                        transform.translation.x -= delta.x * transform.scale.x;
-                        transform.translation.y -= delta.y * transform.scale.y;
-                    }
+                    controls.follow = FollowTarget::Off;
+                    let offset = delta * transform.scale.truncate();
+                    transform.translation.x -= offset.x;
+                    transform.translation.y -= offset.y;
+                    controls.target(&transform).translation -= offset;
                 }
             }
         }
@@ -107,11 +214,206 @@ pub fn handle_mouse_pan_and_zoom(
             })
             .sum::<f32>();
 
-        for mut transform in &mut cameras {
-            let new_scale = transform.scale.x - (scroll_sum * 0.25 * timer.delta_seconds());
-            transform.scale.x = clamp(new_scale, 0.03, 10.0);
-            transform.scale.y = clamp(new_scale, 0.03, 10.0);
+        let factor = (-scroll_sum * 0.002 * controls.zoom_sensitivity).exp();
+        controls.zoom(&transform, factor, cursor.0);
+    }
+}
+
+/// Frames every boid when the fit all binding is pressed.
+pub fn fit_all_boids(
+    cameras: Query<&Transform, With<Camera>>,
+    boids: Query<&Transform, (With<Boid>, Without<Camera>)>,
+    actions: Actions,
+    windows: Res<Windows>,
+    mut controls: ResMut<CameraControls>,
+) {
+    if !actions.just_pressed(Action::FitAll) {
+        return;
+    }
+
+    let (Ok(transform), Some(window)) = (cameras.get_single(), windows.get_primary()) else {
+        return;
+    };
+
+    let bounds = boids.iter().map(|it| it.translation.truncate()).fold(
+        None,
+        |bounds: Option<(Vec2, Vec2)>, pos| {
+            Some(bounds.map_or((pos, pos), |(min, max)| (min.min(pos), max.max(pos))))
+        },
+    );
+    let Some((min, max)) = bounds else {
+        return;
+    };
+
+    controls.follow = FollowTarget::Off;
+    let size = (max - min) * FIT_MARGIN;
+    let target = controls.target(transform);
+    target.translation = (min + max) / 2.0;
+    target.scale = clamp(
+        (size.x / window.width()).max(size.y / window.height()),
+        MIN_SCALE,
+        MAX_SCALE,
+    );
+}
+
+/// Points the camera target at the followed boids.
+pub fn follow_camera(
+    cameras: Query<&Transform, With<Camera>>,
+    boids: Query<(&Boid, &Transform), Without<Camera>>,
+    selection: Res<Selection>,
+    options: Res<Options>,
+    mut controls: ResMut<CameraControls>,
+) {
+    let Ok(transform) = cameras.get_single() else {
+        return;
+    };
+
+    let selected = selection
+        .0
+        .and_then(|it| boids.get(it).ok())
+        .map(|(boid, transform)| (boid.species, transform.translation.truncate()));
+
+    let target = match controls.follow {
+        FollowTarget::Off => None,
+        FollowTarget::Selected => selected.map(|(_, pos)| pos),
+        FollowTarget::Flock => selected.map(|(species, pos)| {
+            // Found from the transforms, so it works the same with either simulation core
+            let (sum, count) = boids
+                .iter()
+                .map(|(boid, transform)| (boid, transform.translation.truncate()))
+                .filter(|(boid, it)| {
+                    boid.species == species && it.distance(pos) < options.visibility_range
+                })
+                .fold((Vec2::ZERO, 0), |(sum, count), (_, it)| {
+                    (sum + it, count + 1)
+                });
+            sum / count.max(1) as f32
+        }),
+        FollowTarget::All => {
+            let count = boids.iter().len();
+            (count > 0).then(|| {
+                boids
+                    .iter()
+                    .map(|(_, transform)| transform.translation.truncate())
+                    .sum::<Vec2>()
+                    / count as f32
+            })
         }
+    };
+
+    if let Some(target) = target {
+        controls.target(transform).translation = target;
+    }
+}
+
+/// Eases the camera towards its target, so zooming and following don't jump.
+pub fn smooth_camera(
+    mut cameras: Query<&mut Transform, With<Camera>>,
+    controls: Res<CameraControls>,
+    timer: Res<Time>,
+) {
+    let Some(target) = controls.target else {
+        return;
+    };
+
+    // Framerate independent exponential smoothing. Position and scale are blended by the same
+    // amount, which keeps the point a zoom is anchored on in place the whole way
+    let t = 1.0 - (-controls.smoothing * timer.delta_seconds()).exp();
+    for mut transform in &mut cameras {
+        let pos = transform.translation.truncate().lerp(target.translation, t);
+        let scale = transform.scale.x + (target.scale - transform.scale.x) * t;
+        transform.translation.x = pos.x;
+        transform.translation.y = pos.y;
+        transform.scale.x = scale;
+        transform.scale.y = scale;
+    }
+}
+
+/// Touches that moved less than this many pixels count as taps.
+const TAP_DISTANCE: f32 = 10.0;
+
+/// World position under a touch. Touch positions start at the top of the window, unlike the
+/// cursor position.
+pub fn touch_to_world(transform: &Transform, window: &Window, pos: Vec2) -> Vec2 {
+    let size = Vec2::new(window.width(), window.height());
+    let screen = Vec2::new(pos.x, size.y - pos.y) - size / 2.0;
+    (transform.compute_matrix() * screen.extend(0.0).extend(1.0))
+        .truncate()
+        .truncate()
+}
+
+/// Position of the touch that was just lifted without moving, if any.
+pub fn tap_position(touches: &Touches) -> Option<Vec2> {
+    touches
+        .iter_just_released()
+        .find(|it| it.distance().length() < TAP_DISTANCE)
+        .map(|it| it.position())
+}
+
+/// Simple system that handles touch input. One finger pans and two fingers zoom around the
+/// point between them, taps are handled by [`crate::inspect::select_boid`].
+pub fn handle_touch_pan_and_zoom(
+    mut cameras: Query<&mut Transform, With<Camera>>,
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut controls: ResMut<CameraControls>,
+) {
+    let (Ok(mut transform), Some(window)) = (cameras.get_single_mut(), windows.get_primary())
+    else {
+        return;
+    };
+
+    // Dragging on the GUI shouldn't move the camera
+    if egui_ctx.ctx_mut().wants_pointer_input() {
+        return;
+    }
+
+    let active = touches.iter().collect::<Vec<_>>();
+    match active[..] {
+        [touch] => {
+            let delta = touch.delta() * Vec2::new(1.0, -1.0);
+            if delta != Vec2::ZERO {
+                controls.follow = FollowTarget::Off;
+                let offset = delta * transform.scale.truncate();
+                transform.translation.x -= offset.x;
+                transform.translation.y -= offset.y;
+                controls.target(&transform).translation -= offset;
+            }
+        }
+        [first, second] => {
+            let previous = first
+                .previous_position()
+                .distance(second.previous_position());
+            let current = first.position().distance(second.position());
+            if previous > 0.0 && current > 0.0 && previous != current {
+                let center = (first.position() + second.position()) / 2.0;
+                let anchor = touch_to_world(&transform, window, center);
+                controls.zoom(&transform, previous / current, Some(anchor));
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Scales the GUI up on touch screens. The window scale factor is overridden to 1, so egui is
+/// drawn in physical pixels and would be tiny on high density phone screens.
+pub fn scale_gui_for_touch(
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    mut egui_settings: ResMut<EguiSettings>,
+) {
+    if touches.iter_just_pressed().next().is_none() {
+        return;
+    }
+
+    let Some(window) = windows.get_primary() else {
+        return;
+    };
+
+    let scale_factor = window.backend_scale_factor().clamp(1.0, 2.0);
+    if egui_settings.scale_factor != scale_factor {
+        egui_settings.scale_factor = scale_factor;
     }
 }
 
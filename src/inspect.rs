@@ -0,0 +1,208 @@
+use bevy::input::touch::Touches;
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Entity, Local, Mesh, Query, Res, ResMut, Transform, Vec2,
+    Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use bevy::window::Windows;
+use bevy_egui::{egui, EguiContext};
+
+use crate::bindings::{Action, Actions};
+use crate::brush::{Brush, BrushTool};
+use crate::debug::{circle, BoidForces, FORCE_LEGEND};
+use crate::input::{tap_position, touch_to_world, Camera, CursorPosition};
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::replay::{SimEvent, SimEvents};
+use crate::{egui_color, Boid};
+
+/// Clicks further than this many pixels away from every boid clear the selection.
+const PICK_RADIUS: f32 = 20.0;
+
+/// Boid picked by clicking on it.
+#[derive(Debug, Default)]
+pub struct Selection(pub Option<Entity>);
+
+/// Marks the entity the ring around the selected boid is drawn through.
+#[derive(Component)]
+pub struct SelectionMesh;
+
+pub fn spawn_selection_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the boids and the debug overlays
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, 0.6))
+        .insert(SelectionMesh);
+}
+
+/// Selects the boid closest to the cursor on a left click or tap, or clears the selection when
+/// there's none nearby.
+pub fn select_boid(
+    query: Query<(Entity, &Transform), With<Boid>>,
+    cameras: Query<&Transform, With<Camera>>,
+    actions: Actions,
+    touches: Res<Touches>,
+    windows: Res<Windows>,
+    cursor: Res<CursorPosition>,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut selection: ResMut<Selection>,
+    mut forces: ResMut<BoidForces>,
+    brush: Res<Brush>,
+) {
+    // Forgetting boids that were deleted or discarded by rewinding
+    if let Some(entity) = selection.0 {
+        if query.get(entity).is_err() {
+            selection.0 = None;
+        }
+    }
+
+    let camera = cameras.get_single().ok();
+    let picked = if actions.just_pressed(Action::Select) {
+        cursor.0
+    } else {
+        let window = windows.get_primary();
+        tap_position(&touches)
+            .zip(camera.zip(window))
+            .map(|(pos, (camera, window))| touch_to_world(camera, window, pos))
+    };
+
+    // Clicks paint instead while a brush is picked
+    if brush.tool == BrushTool::Off && !egui_ctx.ctx_mut().is_pointer_over_area() {
+        if let Some(picked) = picked {
+            let scale = camera.map_or(1.0, |it| it.scale.x);
+            let radius = PICK_RADIUS * scale;
+
+            selection.0 = query
+                .iter()
+                .map(|(entity, transform)| {
+                    let distance = transform.translation.truncate().distance_squared(picked);
+                    (entity, distance)
+                })
+                .filter(|(_, distance)| *distance < radius * radius)
+                .min_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(entity, _)| entity);
+        }
+    }
+
+    if forces.watched() != selection.0 {
+        forces.watch(selection.0);
+    }
+}
+
+/// Draws a ring around the selected boid.
+pub fn draw_selection(
+    query: Query<&Transform, With<Boid>>,
+    mut selection_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<SelectionMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    selection: Res<Selection>,
+    mut drawn: Local<Option<Vec2>>,
+    mut lines: Local<LineMesh>,
+) {
+    let Ok((handle, mut visibility)) = selection_mesh.get_single_mut() else {
+        return;
+    };
+
+    let pos = selection
+        .0
+        .and_then(|it| query.get(it).ok())
+        .map(|it| it.translation.truncate());
+    if *drawn == pos {
+        return;
+    }
+
+    *drawn = pos;
+
+    lines.clear();
+    if let Some(pos) = pos {
+        circle(&mut lines, pos, 2.0, 0.2, Color::WHITE);
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
+
+/// Window showing the state of the selected boid, its velocity can be changed from here or the
+/// boid deleted. Both go through the event queue so they end up in recordings.
+pub fn inspect_gui(
+    query: Query<(&Boid, &Transform)>,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut selection: ResMut<Selection>,
+    mut events: ResMut<SimEvents>,
+    forces: Res<BoidForces>,
+) {
+    let Some(entity) = selection.0 else {
+        return;
+    };
+    let Ok((boid, transform)) = query.get(entity) else {
+        return;
+    };
+
+    let mut open = true;
+    egui::Window::new("Boid")
+        .open(&mut open)
+        .default_width(175.0)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            let pos = transform.translation;
+            let vel = Vec2::new(boid.vx, boid.vy);
+            let heading = vel.y.atan2(vel.x).to_degrees();
+
+            ui.label(format!("Id: {}", boid.id));
+            ui.label(format!("Position: {:.2}, {:.2}", pos.x, pos.y));
+            ui.label(format!("Speed: {:.3}", vel.length()));
+            ui.label(format!("Heading: {heading:.0}°"));
+            ui.label(format!("Flock Size: {}", boid.flock_size));
+
+            ui.horizontal(|ui| {
+                let (mut vx, mut vy) = (boid.vx, boid.vy);
+                ui.label("Velocity");
+                let changed = ui.add(egui::DragValue::new(&mut vx).speed(0.005)).changed()
+                    | ui.add(egui::DragValue::new(&mut vy).speed(0.005)).changed();
+
+                if changed {
+                    events.push(SimEvent::SetVelocity {
+                        id: boid.id,
+                        vx,
+                        vy,
+                    });
+                }
+            });
+
+            let mut leader = boid.leader;
+            if ui.checkbox(&mut leader, "Leader").changed() {
+                events.push(SimEvent::SetLeader {
+                    id: boid.id,
+                    leader,
+                });
+            }
+
+            ui.separator();
+            match forces.get(entity) {
+                Some(forces) => {
+                    ui.label(format!("Neighbors: {}", forces.neighbors.len()));
+                    for (force, (name, color)) in forces.all().into_iter().zip(FORCE_LEGEND) {
+                        ui.colored_label(
+                            egui_color(color),
+                            format!("{name}: {:.4}, {:.4}", force.x, force.y),
+                        );
+                    }
+                }
+                None => {
+                    ui.label("Forces are recorded on the next tick");
+                }
+            }
+
+            ui.separator();
+            if ui.button("Delete").clicked() {
+                events.push(SimEvent::Despawn { id: boid.id });
+            }
+        });
+
+    if !open {
+        selection.0 = None;
+    }
+}
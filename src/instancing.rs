@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+
+use bevy::core_pipeline::core_2d::Transparent2d;
+use bevy::ecs::query::QueryItem;
+use bevy::ecs::system::lifetimeless::{Read, SQuery, SRes};
+use bevy::ecs::system::SystemParamItem;
+use bevy::prelude::{
+    App, Assets, Commands, Component, ComputedVisibility, Entity, FromWorld, GlobalTransform,
+    HandleUntyped, Local, Msaa, Plugin, Query, Res, ResMut, Transform, Vec3, Visibility, With,
+    World,
+};
+use bevy::reflect::TypeUuid;
+use bevy::render::extract_component::{ExtractComponent, ExtractComponentPlugin};
+use bevy::render::mesh::{GpuBufferInfo, Mesh, MeshVertexBufferLayout};
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_phase::{
+    AddRenderCommand, DrawFunctions, EntityRenderCommand, RenderCommandResult, RenderPhase,
+    SetItemPipeline, TrackedRenderPass,
+};
+use bevy::render::render_resource::{
+    Buffer, BufferDescriptor, BufferUsages, PipelineCache, RenderPipelineDescriptor, Shader,
+    SpecializedMeshPipeline, SpecializedMeshPipelineError, SpecializedMeshPipelines,
+    VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
+};
+use bevy::render::renderer::{RenderDevice, RenderQueue};
+use bevy::render::view::NoFrustumCulling;
+use bevy::render::{RenderApp, RenderStage};
+use bevy::sprite::{
+    Mesh2dHandle, Mesh2dPipeline, Mesh2dPipelineKey, Mesh2dUniform, SetMesh2dBindGroup,
+    SetMesh2dViewBindGroup,
+};
+use bevy::utils::FloatOrd;
+use bytemuck::{Pod, Zeroable};
+
+use crate::{boid_color, Boid, BoidMesh, Options};
+
+const BOID_INSTANCING_SHADER_HANDLE: HandleUntyped =
+    HandleUntyped::weak_from_u64(Shader::TYPE_UUID, 0x5D1B_7F0A_93C2_4E61);
+
+/// Whether boids are drawn in a single instanced draw call instead of as one mesh per entity.
+/// Picked on the prompt screen or with `--instanced`, it can't be changed while playing.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InstancedRendering(pub bool);
+
+/// Draws every boid with one instanced draw call. The boid entities are hidden and only used for
+/// the simulation, their position, rotation and color are packed into [`BoidInstances`] on a
+/// single entity every frame instead.
+pub struct BoidInstancingPlugin;
+
+impl Plugin for BoidInstancingPlugin {
+    fn build(&self, app: &mut App) {
+        // Embedding the shader so the WASM build doesn't have to fetch it
+        app.world.resource_mut::<Assets<Shader>>().set_untracked(
+            BOID_INSTANCING_SHADER_HANDLE,
+            Shader::from_wgsl(include_str!("../assets/shaders/boid_instancing.wgsl")),
+        );
+
+        app.add_plugin(ExtractComponentPlugin::<BoidInstances>::default());
+        app.sub_app_mut(RenderApp)
+            .add_render_command::<Transparent2d, DrawBoids>()
+            .init_resource::<BoidInstancingPipeline>()
+            .init_resource::<SpecializedMeshPipelines<BoidInstancingPipeline>>()
+            .add_system_to_stage(RenderStage::Prepare, prepare_boid_instance_buffers)
+            .add_system_to_stage(RenderStage::Queue, queue_boid_instances);
+    }
+}
+
+/// Per boid data uploaded to the GPU, matches the instance attributes in `boid_instancing.wgsl`.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C)]
+struct BoidInstance {
+    /// Translation followed by the rotation around z in radians
+    position_rotation: [f32; 4],
+    /// Linear RGBA, the same values a `ColorMaterial` hands to its shader
+    color: [f32; 4],
+    scale: [f32; 2],
+}
+
+#[derive(Debug, Component, Default, Clone)]
+pub struct BoidInstances(Vec<BoidInstance>);
+
+impl ExtractComponent for BoidInstances {
+    type Query = &'static BoidInstances;
+    type Filter = ();
+
+    fn extract_component(item: QueryItem<'_, Self::Query>) -> Self {
+        item.clone()
+    }
+}
+
+/// Spawns the entity every boid instance is drawn through.
+pub fn spawn_boid_instances(
+    mut commands: Commands,
+    instanced: Res<InstancedRendering>,
+    boid_mesh: Res<BoidMesh>,
+) {
+    if !instanced.0 {
+        return;
+    }
+
+    // The instances are spread over the whole world while the entity sits at the origin, so it
+    // must never be culled
+    commands.spawn_bundle((
+        BoidInstances::default(),
+        boid_mesh.0.clone(),
+        Transform::default(),
+        GlobalTransform::default(),
+        Visibility::default(),
+        ComputedVisibility::default(),
+        NoFrustumCulling,
+    ));
+}
+
+/// Packs the boids into the instance buffer, colored like [`crate::calculate_boid_color`] does.
+pub fn update_boid_instances(
+    mut instances: Query<&mut BoidInstances>,
+    query: Query<(&Boid, &Transform)>,
+    options: Res<Options>,
+) {
+    let Ok(mut instances) = instances.get_single_mut() else {
+        return;
+    };
+
+    instances.0.clear();
+    instances.0.extend(query.iter().map(|(boid, transform)| {
+        let heading = transform.rotation * Vec3::X;
+
+        BoidInstance {
+            position_rotation: [
+                transform.translation.x,
+                transform.translation.y,
+                transform.translation.z,
+                libm::atan2f(heading.y, heading.x),
+            ],
+            color: boid_color(boid, &options).as_linear_rgba_f32(),
+            scale: [transform.scale.x, transform.scale.y],
+        }
+    }));
+}
+
+#[derive(Component)]
+struct BoidInstanceBuffer {
+    buffer: Buffer,
+    length: usize,
+}
+
+/// Writes the instances into a buffer kept across frames, which is only replaced by a larger one
+/// when the boids outgrow it.
+fn prepare_boid_instance_buffers(
+    mut commands: Commands,
+    query: Query<(Entity, &BoidInstances)>,
+    render_device: Res<RenderDevice>,
+    render_queue: Res<RenderQueue>,
+    mut buffers: Local<HashMap<Entity, (Buffer, usize)>>,
+) {
+    buffers.retain(|entity, _| query.get(*entity).is_ok());
+    for (entity, instances) in query.iter() {
+        // Empty buffers aren't allowed, with no boids nothing is queued at all
+        if instances.0.is_empty() {
+            continue;
+        }
+
+        let length = instances.0.len();
+        let (buffer, capacity) = buffers.entry(entity).or_insert_with(|| {
+            let buffer = create_instance_buffer(&render_device, length);
+            (buffer, length)
+        });
+        if *capacity < length {
+            // Growing by half again so spawning a few boids at a time doesn't reallocate each time
+            *capacity = length + length / 2;
+            *buffer = create_instance_buffer(&render_device, *capacity);
+        }
+
+        render_queue.write_buffer(buffer, 0, bytemuck::cast_slice(instances.0.as_slice()));
+        commands.entity(entity).insert(BoidInstanceBuffer {
+            buffer: buffer.clone(),
+            length,
+        });
+    }
+}
+
+fn create_instance_buffer(render_device: &RenderDevice, length: usize) -> Buffer {
+    render_device.create_buffer(&BufferDescriptor {
+        label: Some("boid instance buffer"),
+        size: (length * std::mem::size_of::<BoidInstance>()) as u64,
+        usage: BufferUsages::VERTEX | BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn queue_boid_instances(
+    mut logged_error: Local<bool>,
+    draw_functions: Res<DrawFunctions<Transparent2d>>,
+    pipeline: Res<BoidInstancingPipeline>,
+    msaa: Res<Msaa>,
+    mut pipelines: ResMut<SpecializedMeshPipelines<BoidInstancingPipeline>>,
+    mut pipeline_cache: ResMut<PipelineCache>,
+    meshes: Res<RenderAssets<Mesh>>,
+    instances: Query<(Entity, &Mesh2dHandle, &Mesh2dUniform), With<BoidInstanceBuffer>>,
+    mut views: Query<&mut RenderPhase<Transparent2d>>,
+) {
+    let draw_boids = draw_functions.read().get_id::<DrawBoids>().unwrap();
+    let msaa_key = Mesh2dPipelineKey::from_msaa_samples(msaa.samples);
+
+    for mut transparent_phase in views.iter_mut() {
+        for (entity, mesh_handle, mesh_uniform) in instances.iter() {
+            let Some(mesh) = meshes.get(&mesh_handle.0) else { continue; };
+
+            let key =
+                msaa_key | Mesh2dPipelineKey::from_primitive_topology(mesh.primitive_topology);
+            let pipeline_id =
+                match pipelines.specialize(&mut pipeline_cache, &pipeline, key, &mesh.layout) {
+                    Ok(id) => id,
+                    Err(e) => {
+                        // Specializing fails the same way every frame, so it's only logged once
+                        if !*logged_error {
+                            *logged_error = true;
+                            eprintln!("Error: {e}");
+                        }
+
+                        continue;
+                    }
+                };
+
+            transparent_phase.add(Transparent2d {
+                sort_key: FloatOrd(mesh_uniform.transform.w_axis.z),
+                entity,
+                pipeline: pipeline_id,
+                draw_function: draw_boids,
+                batch_range: None,
+            });
+        }
+    }
+}
+
+/// The regular 2D mesh pipeline with an extra per instance vertex buffer and our own shader.
+struct BoidInstancingPipeline {
+    mesh2d_pipeline: Mesh2dPipeline,
+}
+
+impl FromWorld for BoidInstancingPipeline {
+    fn from_world(world: &mut World) -> Self {
+        Self {
+            mesh2d_pipeline: world.resource::<Mesh2dPipeline>().clone(),
+        }
+    }
+}
+
+impl SpecializedMeshPipeline for BoidInstancingPipeline {
+    type Key = Mesh2dPipelineKey;
+
+    fn specialize(
+        &self,
+        key: Self::Key,
+        layout: &MeshVertexBufferLayout,
+    ) -> Result<RenderPipelineDescriptor, SpecializedMeshPipelineError> {
+        let mut descriptor = self.mesh2d_pipeline.specialize(key, layout)?;
+        let shader = BOID_INSTANCING_SHADER_HANDLE.typed::<Shader>();
+
+        // Locations up to 4 are used by the mesh attributes of the 2D mesh pipeline
+        descriptor.vertex.shader = shader.clone();
+        descriptor.vertex.buffers.push(VertexBufferLayout {
+            array_stride: std::mem::size_of::<BoidInstance>() as u64,
+            step_mode: VertexStepMode::Instance,
+            attributes: vec![
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: 0,
+                    shader_location: 5,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x4,
+                    offset: VertexFormat::Float32x4.size(),
+                    shader_location: 6,
+                },
+                VertexAttribute {
+                    format: VertexFormat::Float32x2,
+                    offset: VertexFormat::Float32x4.size() * 2,
+                    shader_location: 7,
+                },
+            ],
+        });
+
+        if let Some(fragment) = descriptor.fragment.as_mut() {
+            fragment.shader = shader;
+        }
+
+        Ok(descriptor)
+    }
+}
+
+type DrawBoids = (
+    SetItemPipeline,
+    SetMesh2dViewBindGroup<0>,
+    SetMesh2dBindGroup<1>,
+    DrawBoidInstances,
+);
+
+struct DrawBoidInstances;
+
+impl EntityRenderCommand for DrawBoidInstances {
+    type Param = (
+        SRes<RenderAssets<Mesh>>,
+        SQuery<Read<Mesh2dHandle>>,
+        SQuery<Read<BoidInstanceBuffer>>,
+    );
+
+    fn render<'w>(
+        _view: Entity,
+        item: Entity,
+        (meshes, mesh_query, instance_buffer_query): SystemParamItem<'w, '_, Self::Param>,
+        pass: &mut TrackedRenderPass<'w>,
+    ) -> RenderCommandResult {
+        let (Ok(mesh_handle), Ok(instance_buffer)) =
+            (mesh_query.get(item), instance_buffer_query.get_inner(item))
+        else {
+            return RenderCommandResult::Failure;
+        };
+
+        let Some(gpu_mesh) = meshes.into_inner().get(&mesh_handle.0) else {
+            return RenderCommandResult::Failure;
+        };
+
+        let instances = 0..instance_buffer.length as u32;
+        pass.set_vertex_buffer(0, gpu_mesh.vertex_buffer.slice(..));
+        pass.set_vertex_buffer(1, instance_buffer.buffer.slice(..));
+
+        match &gpu_mesh.buffer_info {
+            GpuBufferInfo::Indexed {
+                buffer,
+                index_format,
+                count,
+            } => {
+                pass.set_index_buffer(buffer.slice(..), 0, *index_format);
+                pass.draw_indexed(0..*count, 0, instances);
+            }
+            GpuBufferInfo::NonIndexed { vertex_count } => {
+                pass.draw(0..*vertex_count, instances);
+            }
+        }
+
+        RenderCommandResult::Success
+    }
+}
@@ -0,0 +1,46 @@
+//! Leaders are boids that flockmates weigh more heavily and that can be steered, so a few of them
+//! can guide the whole flock.
+
+use bevy::math::Vec2;
+
+/// Way the leaders are steered on the current tick.
+#[derive(Debug, Default, Clone, Copy)]
+pub enum LeaderSteering {
+    /// Leaders only flock like everyone else
+    #[default]
+    None,
+    /// Towards a direction
+    Heading(Vec2),
+    /// Towards a point
+    Towards(Vec2),
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LeaderParams {
+    /// How many ordinary flockmates a visible leader counts as for alignment and cohesion
+    pub weight: f32,
+    pub impact: f32,
+    pub steering: LeaderSteering,
+}
+
+impl LeaderParams {
+    /// Weight a neighbor has in the averages of the boids that see it.
+    pub fn weight_of(&self, leader: bool) -> f32 {
+        if leader {
+            self.weight
+        } else {
+            1.0
+        }
+    }
+
+    /// Velocity change of a leader at `pos`.
+    pub fn steer(&self, pos: Vec2) -> Vec2 {
+        let dir = match self.steering {
+            LeaderSteering::None => return Vec2::ZERO,
+            LeaderSteering::Heading(dir) => dir,
+            LeaderSteering::Towards(target) => target - pos,
+        };
+
+        dir.normalize_or_zero() * self.impact
+    }
+}
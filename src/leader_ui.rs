@@ -0,0 +1,227 @@
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Entity, Local, Mesh, Query, Res, ResMut, Transform, Vec2,
+    Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use bevy_egui::{egui, EguiContext};
+use boids::leader::{LeaderParams, LeaderSteering};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{Action, Actions};
+use crate::debug::circle;
+use crate::inspect::Selection;
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::migration::route_position;
+use crate::replay::{SimEvent, SimEvents};
+use crate::{egui_color, Boid, Options, State};
+
+const LEADER_COLOR: Color = Color::rgb(1.0, 0.8, 0.2);
+
+/// How the leaders are steered on top of flocking.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LeaderControl {
+    #[default]
+    Free,
+    Keyboard,
+    /// Along the migration route, half a border ahead of the border center
+    Route,
+}
+
+impl LeaderControl {
+    pub const ALL: [Self; 3] = [Self::Free, Self::Keyboard, Self::Route];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Free => "Free",
+            Self::Keyboard => "Keyboard",
+            Self::Route => "Migration Route",
+        }
+    }
+}
+
+/// Weight of the leaders and how they're steered on the current tick.
+pub fn leader_params(options: &Options, state: &State) -> LeaderParams {
+    let steering = match options.leader_control {
+        LeaderControl::Free => LeaderSteering::None,
+        LeaderControl::Keyboard => state
+            .leader_heading
+            .map_or(LeaderSteering::None, LeaderSteering::Heading),
+        LeaderControl::Route => {
+            let ahead = state.migration_distance + options.border_size as f32 * 0.5;
+            LeaderSteering::Towards(route_position(options, ahead))
+        }
+    };
+
+    LeaderParams {
+        weight: options.leader_weight,
+        impact: options.leader_impact,
+        steering,
+    }
+}
+
+/// Turns the leader steering keys into a heading, which is only sent when it changes so
+/// recordings don't fill up with it.
+pub fn steer_leaders(
+    actions: Actions,
+    options: Res<Options>,
+    mut events: ResMut<SimEvents>,
+    mut sent: Local<Option<Vec2>>,
+) {
+    if options.leader_control != LeaderControl::Keyboard {
+        return;
+    }
+
+    let mut heading = Vec2::ZERO;
+    if actions.pressed(Action::LeaderUp) {
+        heading.y += 1.0;
+    }
+
+    if actions.pressed(Action::LeaderLeft) {
+        heading.x -= 1.0;
+    }
+
+    if actions.pressed(Action::LeaderDown) {
+        heading.y -= 1.0;
+    }
+
+    if actions.pressed(Action::LeaderRight) {
+        heading.x += 1.0;
+    }
+
+    let heading = heading.try_normalize();
+    if heading != *sent {
+        *sent = heading;
+        events.push(SimEvent::SteerLeaders(heading.map(|it| it.to_array())));
+    }
+}
+
+/// Settings of the leaders in the main window.
+pub fn leader_ui(ui: &mut egui::Ui, options: &mut Options) {
+    ui.horizontal(|ui| {
+        ui.label("Leader Weight");
+        ui.add(egui::DragValue::new(&mut options.leader_weight).clamp_range(1.0..=100.0));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Leader Impact");
+        ui.add(
+            egui::DragValue::new(&mut options.leader_impact)
+                .speed(0.001)
+                .clamp_range(0.0..=0.5),
+        );
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Leader Control");
+        egui::ComboBox::from_id_source("leader_control")
+            .selected_text(options.leader_control.name())
+            .show_ui(ui, |ui| {
+                for control in LeaderControl::ALL {
+                    ui.selectable_value(&mut options.leader_control, control, control.name());
+                }
+            });
+    });
+}
+
+/// Window listing the leaders with the number of flockmates that can see them, shown while there
+/// are any.
+pub fn leaders_gui(
+    query: Query<(Entity, &Boid, &Transform)>,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut selection: ResMut<Selection>,
+    mut events: ResMut<SimEvents>,
+    options: Res<Options>,
+) {
+    let mut leaders = query
+        .iter()
+        .filter(|(_, boid, _)| boid.leader)
+        .collect::<Vec<_>>();
+    if leaders.is_empty() {
+        return;
+    }
+
+    leaders.sort_unstable_by_key(|(_, boid, _)| boid.id);
+
+    egui::Window::new("Leaders")
+        .default_open(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            for (entity, leader, leader_transform) in leaders {
+                let pos = leader_transform.translation.truncate();
+                let followers = query
+                    .iter()
+                    .filter(|(_, boid, transform)| {
+                        !boid.leader
+                            && boid.species == leader.species
+                            && transform.translation.truncate().distance(pos)
+                                < options.visibility_range
+                    })
+                    .count();
+
+                ui.horizontal(|ui| {
+                    let label = format!("Boid {}: {followers} followers", leader.id);
+                    ui.colored_label(egui_color(LEADER_COLOR), label);
+
+                    if ui.small_button("Select").clicked() {
+                        selection.0 = Some(entity);
+                    }
+
+                    if ui.small_button("✖").clicked() {
+                        events.push(SimEvent::SetLeader {
+                            id: leader.id,
+                            leader: false,
+                        });
+                    }
+                });
+            }
+        });
+}
+
+/// Marks the entity the rings around the leaders are drawn through.
+#[derive(Component)]
+pub struct LeaderMesh;
+
+pub fn spawn_leader_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the boids, like the selection ring
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, 0.6))
+        .insert(LeaderMesh);
+}
+
+/// Draws a ring around every leader.
+pub fn draw_leaders(
+    query: Query<(&Boid, &Transform)>,
+    mut leader_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<LeaderMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut lines: Local<LineMesh>,
+) {
+    let Ok((handle, mut visibility)) = leader_mesh.get_single_mut() else {
+        return;
+    };
+
+    let was_empty = lines.is_empty();
+    lines.clear();
+    for (_, transform) in query.iter().filter(|(boid, _)| boid.leader) {
+        circle(
+            &mut lines,
+            transform.translation.truncate(),
+            1.5,
+            0.15,
+            LEADER_COLOR,
+        );
+    }
+
+    // Leaders move every tick, so only a flock without any is skipped
+    if was_empty && lines.is_empty() {
+        return;
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
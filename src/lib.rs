@@ -0,0 +1,17 @@
+//! Simulation code that doesn't depend on the app, shared with the benchmarks.
+
+#![warn(
+    clippy::wildcard_imports,
+    clippy::string_add,
+    clippy::string_add_assign,
+    clippy::manual_ok_or,
+    unused_lifetimes
+)]
+
+pub mod flow;
+pub mod goal;
+pub mod grid;
+pub mod leader;
+pub mod rules;
+pub mod soa;
+pub mod wander;
@@ -0,0 +1,151 @@
+use bevy::prelude::{
+    Assets, Color, FromWorld, Handle, Image, Mesh, Transform, Vec2, Visibility, World,
+};
+use bevy::render::mesh::{Indices, PrimitiveTopology};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::sprite::{ColorMaterial, MaterialMesh2dBundle};
+
+/// Material for meshes colored per vertex. Bevy only applies vertex colors to a `ColorMaterial`
+/// that has a texture, so it gets a single white pixel.
+pub struct LineMaterial(pub Handle<ColorMaterial>);
+
+impl FromWorld for LineMaterial {
+    fn from_world(world: &mut World) -> Self {
+        let texture = world.resource_mut::<Assets<Image>>().add(Image::new_fill(
+            Extent3d {
+                width: 1,
+                height: 1,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            &[255, 255, 255, 255],
+            TextureFormat::Rgba8UnormSrgb,
+        ));
+
+        Self(
+            world
+                .resource_mut::<Assets<ColorMaterial>>()
+                .add(ColorMaterial {
+                    color: Color::WHITE,
+                    texture: Some(texture),
+                }),
+        )
+    }
+}
+
+/// Entity drawing a [`LineMesh`], placed at `z` so it can go under or over the boids.
+pub fn line_mesh_bundle(
+    meshes: &mut Assets<Mesh>,
+    material: &LineMaterial,
+    z: f32,
+) -> MaterialMesh2dBundle<ColorMaterial> {
+    MaterialMesh2dBundle {
+        mesh: meshes.add(LineMesh::default().into_mesh()).into(),
+        material: material.0.clone(),
+        transform: Transform::from_xyz(0.0, 0.0, z),
+        visibility: Visibility { is_visible: false },
+        ..Default::default()
+    }
+}
+
+/// Batches thick line segments into the triangles of a single mesh, so thousands of lines are
+/// drawn with one draw call.
+#[derive(Debug, Default)]
+pub struct LineMesh {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+    indices: Vec<u32>,
+}
+
+impl LineMesh {
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+        self.colors.clear();
+        self.indices.clear();
+    }
+
+    /// Adds a segment `width` wide, blending from `from_color` to `to_color` along its length.
+    pub fn segment(
+        &mut self,
+        from: Vec2,
+        to: Vec2,
+        width: f32,
+        from_color: Color,
+        to_color: Color,
+    ) {
+        let Some(dir) = (to - from).try_normalize() else {
+            return;
+        };
+
+        let side = dir.perp() * (width * 0.5);
+        let from_color = from_color.as_linear_rgba_f32();
+        let to_color = to_color.as_linear_rgba_f32();
+
+        self.push_quad(
+            [from - side, to - side, to + side, from + side],
+            [from_color, to_color, to_color, from_color],
+        );
+    }
+
+    /// Adds a segment with a triangular head pointing at `to`.
+    pub fn arrow(&mut self, from: Vec2, to: Vec2, width: f32, color: Color) {
+        let Some(dir) = (to - from).try_normalize() else {
+            return;
+        };
+
+        let head_length = (width * 3.0).min(from.distance(to) * 0.5);
+        let base = to - dir * head_length;
+        self.segment(from, base, width, color, color);
+
+        let side = dir.perp() * (width * 1.5);
+        let color = color.as_linear_rgba_f32();
+        let first = self.positions.len() as u32;
+        self.positions.extend(
+            [base - side, to, base + side]
+                .iter()
+                .map(|it| [it.x, it.y, 0.0]),
+        );
+        self.colors.extend([color; 3]);
+        self.indices.extend([first, first + 1, first + 2]);
+    }
+
+    /// Adds a filled axis aligned rectangle.
+    pub fn rect(&mut self, min: Vec2, max: Vec2, color: Color) {
+        let color = color.as_linear_rgba_f32();
+        self.push_quad(
+            [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)],
+            [color; 4],
+        );
+    }
+
+    /// Corners have to go counterclockwise, the 2D mesh pipeline culls back faces.
+    fn push_quad(&mut self, corners: [Vec2; 4], colors: [[f32; 4]; 4]) {
+        let first = self.positions.len() as u32;
+        self.positions
+            .extend(corners.iter().map(|it| [it.x, it.y, 0.0]));
+        self.colors.extend(colors);
+        self.indices
+            .extend([first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+
+    /// Replaces the contents of `mesh` with the segments added so far.
+    pub fn write_to(&self, mesh: &mut Mesh) {
+        // The 2D mesh pipeline needs normals and UVs even though lines don't use them
+        let len = self.positions.len();
+        mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions.clone());
+        mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0.0, 0.0, 1.0]; len]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0.0, 0.0]; len]);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors.clone());
+        mesh.set_indices(Some(Indices::U32(self.indices.clone())));
+    }
+
+    fn into_mesh(self) -> Mesh {
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        self.write_to(&mut mesh);
+        mesh
+    }
+}
@@ -7,30 +7,89 @@
     unused_lifetimes
 )]
 
+mod bench;
+mod bindings;
+mod brush;
+mod debug;
+mod flow_ui;
+mod formation;
+mod gamepad;
+mod goal_ui;
+mod heatmap;
+mod history;
 mod input;
-
-use std::f32::consts::PI;
+mod inspect;
+mod instancing;
+mod leader_ui;
+mod lines;
+mod migration;
+mod minimap;
+mod replay;
+mod trails;
+
+use std::collections::{HashMap, HashSet};
+use std::f32::consts::{PI, TAU};
 
 use bevy::log::{Level, LogSettings};
 use bevy::prelude::{
-    shape, App, Assets, Bundle, Camera2dBundle, ClearColor, Color, Commands, Component,
-    ComputedVisibility, Entity, GlobalTransform, Handle, Input, KeyCode, Mesh, Quat, Query, Res,
-    ResMut, SystemSet, Transform, Vec3, Visibility,
+    shape, Added, App, Assets, Bundle, Camera2dBundle, ClearColor, Color, Commands, Component,
+    ComputedVisibility, Entity, GlobalTransform, Handle, Mesh, Quat, Query, Res, ResMut, SystemSet,
+    Transform, Vec2, Vec3, Visibility,
 };
 use bevy::sprite::{ColorMaterial, Mesh2dHandle};
-use bevy::time::FixedTimestep;
-use bevy::window::WindowDescriptor;
-use bevy::DefaultPlugins;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use bevy::tasks::{ComputeTaskPool, ParallelSlice};
+use bevy::time::{FixedTimestep, Time};
+use bevy::window::{PresentMode, WindowDescriptor};
+use bevy::{DefaultPlugins, MinimalPlugins};
 use bevy_egui::{egui, EguiContext, EguiPlugin};
 #[cfg(debug_assertions)]
 use bevy_inspector_egui::WorldInspectorPlugin;
-use bevy_spatial::{KDTreeAccess2D, KDTreePlugin2D, SpatialAccess};
-use libm::sqrt;
+use bevy_spatial::{KDTreeAccess2D, SpatialAccess};
+use boids::flow::FlowPattern;
+use boids::goal::Goal;
+use boids::grid::{GridEntry, SpatialGrid};
+use boids::rules::{step_boid, Forces};
+use boids::soa::{SoaFlock, SoaParams};
+use boids::wander::WanderParams;
 use num::clamp;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::bindings::{Action, Actions, BindingEditor, KeyBindings};
+use crate::brush::{Brush, BrushHeading, BrushTool};
+use crate::debug::{BoidForces, FORCE_LEGEND};
+use crate::flow_ui::FlowGrid;
+use crate::formation::Formation;
+use crate::heatmap::HeatmapRamp;
+use crate::history::{History, HistoryFrame, HISTORY_INTERVAL};
+use crate::input::{Camera, CameraControls, CursorPanState, CursorPlugin, FollowTarget};
+use crate::inspect::Selection;
+use crate::instancing::{BoidInstancingPlugin, InstancedRendering};
+use crate::leader_ui::LeaderControl;
+use crate::lines::LineMaterial;
+use crate::migration::MigrationRoute;
+use crate::replay::{Recorder, Recording, Replay, SimEvent, SimEvents};
+
+/// Kd-tree the kd-tree neighbor backend rebuilds at the start of every tick. It's only a
+/// resource, the plugin that would also rebuild it every frame isn't added.
+type BoidNNTree = KDTreeAccess2D<Boid>;
 
-use crate::input::{Camera, CursorPanState, CursorPlugin};
+/// Number of simulation ticks in one second of simulated time.
+const TICK_RATE: f64 = 60.0;
 
-type BoidNNTree = KDTreeAccess2D<Boid>;
+/// Rate the colors, rotations, stats and heatmap are updated at.
+const STATS_RATE: f64 = 15.0;
+
+/// Migration moves the border every this many ticks.
+const MIGRATION_INTERVAL: u64 = 6;
+
+/// Number of boids handed to each task when ticking in parallel.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+const PARALLEL_CHUNK_SIZE: usize = 512;
+
+const BOID_SCALE: Vec3 = Vec3::new(0.7, 1.1, 1.0);
 
 fn main() {
     #[cfg(target_arch = "wasm32")]
@@ -39,6 +98,51 @@ fn main() {
         std::panic::set_hook(Box::new(console_error_panic_hook::hook));
     }
 
+    let args = replay::Args::from_env();
+    let recording = args
+        .replay
+        .as_deref()
+        .and_then(|path| match Recording::load(path) {
+            Ok(recording) => Some(recording),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                None
+            }
+        });
+
+    if args.bench {
+        bench::run_neighbor_benchmark();
+        return;
+    }
+
+    // The render benchmark starts right away and measures frames without vsync
+    let mut options = Options::default();
+    let mut present_mode = PresentMode::Fifo;
+    if args.bench_render {
+        options.paused = false;
+        present_mode = PresentMode::Immediate;
+    }
+
+    if args.headless {
+        match recording {
+            Some(recording) => replay::run_headless(recording),
+            None => eprintln!("Error: --headless requires a recording passed with --replay"),
+        }
+
+        return;
+    }
+
+    // Skipping the prompt when a recording was passed on the command line
+    let mut replay = Replay::default();
+    let initial_stage = match recording {
+        Some(recording) => {
+            replay.play(recording);
+            Stage::Playing
+        }
+        None if args.bench_render => Stage::Playing,
+        None => Stage::Prompt,
+    };
+
     let mut app = App::new();
 
     // Constructing our app
@@ -47,6 +151,7 @@ fn main() {
         width: 1280.0,
         height: 720.0,
         scale_factor_override: Some(1.0),
+        present_mode,
         ..Default::default()
     })
     .insert_resource(LogSettings {
@@ -55,41 +160,104 @@ fn main() {
     })
     .insert_resource(ClearColor(Color::BLACK))
     .insert_resource(CursorPanState::default())
-    .insert_resource(Options::default())
+    .insert_resource(CameraControls::default())
+    .insert_resource(KeyBindings::load())
+    .insert_resource(BindingEditor::default())
+    .insert_resource(Brush::default())
+    .insert_resource(options)
     .insert_resource(State::default())
-    .add_state(Stage::Prompt)
+    .insert_resource(History::default())
+    .insert_resource(SimRng::new(0))
+    .insert_resource(SimEvents::default())
+    .insert_resource(FlowGrid::default())
+    .insert_resource(Recorder::default())
+    .insert_resource(replay)
+    .insert_resource(SoaFlock::default())
+    .insert_resource(BoidForces::default())
+    .insert_resource(LastGrid::default())
+    .insert_resource(Selection::default())
+    .insert_resource(InstancedRendering(args.instanced))
+    .init_resource::<BoidNNTree>()
+    .add_state(initial_stage)
     .add_plugins(DefaultPlugins)
-    .add_plugin(KDTreePlugin2D::<Boid>::default())
     .add_plugin(CursorPlugin)
     .add_plugin(EguiPlugin)
+    .add_plugin(BoidInstancingPlugin)
+    .init_resource::<LineMaterial>()
     .add_startup_system(startup)
     .add_system_set(SystemSet::on_update(Stage::Prompt).with_system(prompt_gui))
-    .add_system_set(SystemSet::on_enter(Stage::Playing).with_system(init_world))
     .add_system_set(
-        SystemSet::on_update(Stage::Playing)
-            .with_system(input::handle_keyboard_pan_and_zoom)
-            .with_system(input::handle_mouse_pan_and_zoom)
-            .with_system(handle_play_pause)
-            .with_system(cgol_gui),
+        SystemSet::on_enter(Stage::Playing)
+            .with_system(init_world)
+            .with_system(instancing::spawn_boid_instances)
+            .with_system(trails::spawn_trail_mesh)
+            .with_system(heatmap::spawn_heatmap_mesh)
+            .with_system(debug::spawn_debug_mesh)
+            .with_system(inspect::spawn_selection_mesh)
+            .with_system(gamepad::spawn_attractor_mesh)
+            .with_system(brush::spawn_brush_mesh)
+            .with_system(migration::spawn_migration_mesh)
+            .with_system(flow_ui::spawn_flow_mesh)
+            .with_system(goal_ui::spawn_goal_mesh)
+            .with_system(leader_ui::spawn_leader_mesh),
     )
     .add_system_set(
         SystemSet::on_update(Stage::Playing)
-            .with_run_criteria(FixedTimestep::steps_per_second(10.0))
-            .with_system(migrate),
+            .with_system(input::handle_keyboard_pan_and_zoom)
+            .with_system(input::handle_mouse_pan_and_zoom)
+            .with_system(input::handle_touch_pan_and_zoom)
+            .with_system(input::scale_gui_for_touch)
+            .with_system(gamepad::handle_gamepad)
+            .with_system(bindings::capture_binding)
+            .with_system(bindings::bindings_gui)
+            .with_system(brush::paint)
+            .with_system(brush::draw_brush)
+            .with_system(migration::draw_migration_route)
+            .with_system(flow_ui::draw_flow_arrows)
+            .with_system(goal_ui::draw_goals)
+            .with_system(leader_ui::steer_leaders)
+            .with_system(leader_ui::draw_leaders)
+            .with_system(gamepad::draw_attractor)
+            .with_system(input::fit_all_boids)
+            .with_system(input::follow_camera)
+            .with_system(input::smooth_camera)
+            .with_system(handle_time_controls)
+            .with_system(history::restore_history_frame)
+            .with_system(init_boid_visuals)
+            .with_system(instancing::update_boid_instances)
+            .with_system(trails::init_trails)
+            .with_system(trails::update_trails)
+            .with_system(debug::draw_debug_overlays)
+            .with_system(inspect::select_boid)
+            .with_system(inspect::draw_selection)
+            .with_system(inspect::inspect_gui)
+            .with_system(minimap::minimap_gui)
+            .with_system(cgol_gui)
+            .with_system(replay::session_gui)
+            .with_system(flow_ui::flow_gui)
+            .with_system(goal_ui::goals_gui)
+            .with_system(leader_ui::leaders_gui),
     )
     .add_system_set(
         SystemSet::on_update(Stage::Playing)
-            .with_run_criteria(FixedTimestep::steps_per_second(15.0))
+            .with_run_criteria(FixedTimestep::steps_per_second(STATS_RATE))
             .with_system(calculate_boid_color)
             .with_system(calculate_boid_rotation)
-            .with_system(update_stats),
+            .with_system(update_stats)
+            .with_system(heatmap::update_heatmap),
     )
     .add_system_set(
         SystemSet::on_update(Stage::Playing)
-            .with_run_criteria(FixedTimestep::steps_per_second(60.0))
+            .with_run_criteria(FixedTimestep::steps_per_second(TICK_RATE))
             .with_system(tick_boids),
     );
 
+    if args.bench_render {
+        app.add_system_set(
+            SystemSet::on_update(Stage::Playing).with_system(bench::run_render_benchmark),
+        );
+    }
+
     #[cfg(debug_assertions)]
     {
         // Adding the world inspector if debug mode is enabled
@@ -106,7 +274,41 @@ fn main() {
     app.run();
 }
 
-fn startup(mut commands: Commands) {
+/// Builds an app that runs the simulation without a window, one tick per update.
+fn headless_app(options: Options) -> App {
+    let mut app = App::new();
+    app.insert_resource(options)
+        .insert_resource(State::default())
+        .insert_resource(History::default())
+        .insert_resource(SimRng::new(0))
+        .insert_resource(SimEvents::default())
+        .insert_resource(FlowGrid::default())
+        .insert_resource(Recorder::default())
+        .insert_resource(Replay::default())
+        .insert_resource(SoaFlock::default())
+        .insert_resource(BoidForces::default())
+        .insert_resource(LastGrid::default())
+        .insert_resource(Selection::default())
+        .init_resource::<BoidNNTree>()
+        .add_plugins(MinimalPlugins)
+        .add_system(tick_boids);
+
+    app
+}
+
+fn startup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    options: Res<Options>,
+) {
+    commands.insert_resource(BoidMesh(
+        meshes
+            .add(Mesh::from(shape::RegularPolygon::new(0.5, 3)))
+            .into(),
+    ));
+    commands.insert_resource(BoidPalette::new(&mut materials, options.foreground_color));
+
     commands
         .spawn_bundle(Camera2dBundle {
             transform: Transform::default().with_scale(Vec3 {
@@ -119,24 +321,16 @@ fn startup(mut commands: Commands) {
         .insert(Camera);
 }
 
-fn init_world(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    options: Res<Options>,
-) {
-    let mesh: Mesh2dHandle = meshes
-        .add(Mesh::from(shape::RegularPolygon::new(0.5, 3)))
-        .into();
-
-    for _ in 0..100 {
-        spawn_boid(&mut commands, &mut materials, &options, mesh.clone());
-    }
+fn init_world(mut events: ResMut<SimEvents>, mut rng: ResMut<SimRng>, options: Res<Options>) {
+    *rng = SimRng::new(options.seed);
+    events.push(SimEvent::spawn(options.initial_amount as u32, &options));
 }
 
 fn prompt_gui(
     mut app_state: ResMut<bevy::prelude::State<Stage>>,
     mut egui_ctx: ResMut<EguiContext>,
+    mut instanced: ResMut<InstancedRendering>,
+    mut options: ResMut<Options>,
 ) {
     egui::Window::new("Options")
         .vscroll(true)
@@ -144,6 +338,17 @@ fn prompt_gui(
         .resizable(false)
         .show(egui_ctx.ctx_mut(), |ui| {
             ui.text_edit_multiline(&mut "It is fall and birds are migrating, watch them migrate");
+            ui.checkbox(&mut instanced.0, "Instanced Rendering");
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Boids");
+                ui.add(egui::DragValue::new(&mut options.initial_amount).clamp_range(1..=100_000));
+            });
+
+            formation_ui(ui, &mut options);
+
+            ui.separator();
             if ui.button("Proceed").clicked() {
                 if let Err(e) = app_state.set(Stage::Playing) {
                     eprintln!("Error: {e}");
@@ -152,14 +357,45 @@ fn prompt_gui(
         });
 }
 
+/// Formation, speed and number of leaders of newly spawned boids.
+fn formation_ui(ui: &mut egui::Ui, options: &mut Options) {
+    ui.horizontal(|ui| {
+        ui.label("Formation");
+        egui::ComboBox::from_id_source("formation")
+            .selected_text(options.formation.name())
+            .show_ui(ui, |ui| {
+                for formation in Formation::ALL {
+                    ui.selectable_value(&mut options.formation, formation, formation.name());
+                }
+            });
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Initial Speed");
+        ui.add(
+            egui::DragValue::new(&mut options.spawn_speed)
+                .speed(0.01)
+                .fixed_decimals(2)
+                .clamp_range(0.0..=5.0),
+        );
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Leaders");
+        ui.add(egui::DragValue::new(&mut options.spawn_leaders).clamp_range(0..=100));
+    });
+}
+
 fn cgol_gui(
     mut egui_ctx: ResMut<EguiContext>,
     mut options: ResMut<Options>,
     mut state: ResMut<State>,
+    mut history: ResMut<History>,
     mut background: ResMut<ClearColor>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut events: ResMut<SimEvents>,
+    mut camera: ResMut<CameraControls>,
+    mut binding_editor: ResMut<BindingEditor>,
+    mut brush: ResMut<Brush>,
 ) {
     egui::Window::new("Options")
         .vscroll(true)
@@ -176,6 +412,57 @@ fn cgol_gui(
                 options.paused = !options.paused;
             };
 
+            ui.horizontal(|ui| {
+                if ui.button("Step").clicked() {
+                    options.paused = true;
+                    state.pending_steps += 1;
+                }
+
+                if ui.button("Step N").clicked() {
+                    options.paused = true;
+                    state.pending_steps += options.step_amount;
+                }
+
+                ui.add(egui::DragValue::new(&mut options.step_amount).clamp_range(1..=1000));
+            });
+
+            ui.checkbox(&mut options.fast_forward, "Fast Forward");
+
+            ui.horizontal(|ui| {
+                ui.label("Ticks Per Step");
+                ui.add(egui::DragValue::new(&mut options.fast_forward_ticks).clamp_range(2..=50));
+            });
+
+            ui.label(format!("Tick: {}", state.tick));
+            ui.label(format!("Ticks Per Second: {:.1}", state.ticks_per_second));
+
+            ui.separator();
+            ui.checkbox(&mut options.history, "Record History");
+
+            ui.horizontal(|ui| {
+                ui.label("History Length");
+                ui.add(
+                    egui::DragValue::new(&mut options.history_seconds)
+                        .suffix("s")
+                        .clamp_range(1..=60),
+                );
+            });
+
+            if !history.is_empty() {
+                let last = history.len() - 1;
+                let mut index = history.cursor().unwrap_or(last);
+                if ui
+                    .add(egui::Slider::new(&mut index, 0..=last).show_value(false))
+                    .changed()
+                {
+                    options.paused = true;
+                    history.scrub_to(index);
+                }
+
+                let megabytes = history.bytes() as f32 / (1024.0 * 1024.0);
+                ui.label(format!("{} frames, {megabytes:.1} MB", history.len()));
+            }
+
             ui.separator();
 
             ui.horizontal(|ui| {
@@ -190,6 +477,35 @@ fn cgol_gui(
                 ui.add(egui::DragValue::new(&mut options.accuracy).clamp_range(1..=120));
             });
 
+            ui.horizontal(|ui| {
+                ui.label("Simulation Core");
+                egui::ComboBox::from_id_source("simulation_core")
+                    .selected_text(options.simulation_core.name())
+                    .show_ui(ui, |ui| {
+                        for core in SimulationCore::ALL {
+                            ui.selectable_value(&mut options.simulation_core, core, core.name());
+                        }
+                    });
+            });
+
+            // The structure of arrays core always searches its own grid
+            ui.add_enabled_ui(options.simulation_core == SimulationCore::Ecs, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Neighbor Search");
+                    egui::ComboBox::from_id_source("neighbor_backend")
+                        .selected_text(options.neighbor_backend.name())
+                        .show_ui(ui, |ui| {
+                            for backend in NeighborBackend::ALL {
+                                ui.selectable_value(
+                                    &mut options.neighbor_backend,
+                                    backend,
+                                    backend.name(),
+                                );
+                            }
+                        });
+                });
+            });
+
             ui.separator();
             ui.checkbox(&mut options.separation, "Separation");
 
@@ -223,6 +539,7 @@ fn cgol_gui(
                 ui.label("Cohesion Impact");
                 ui.add(
                     egui::DragValue::new(&mut options.cohesion_impact)
+                        .speed(0.0001)
                         .fixed_decimals(4)
                         .clamp_range(0.0001..=5.0),
                 );
@@ -241,6 +558,63 @@ fn cgol_gui(
                 ui.add(egui::DragValue::new(&mut options.border_impact).clamp_range(0.05..=5.0));
             });
 
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Attractor Impact");
+                ui.add(
+                    egui::DragValue::new(&mut options.attractor_impact)
+                        .speed(0.001)
+                        .clamp_range(0.0..=0.5),
+                );
+            });
+
+            if state.attractor.is_some() && ui.button("Remove Attractor").clicked() {
+                events.push(SimEvent::MoveAttractor(None));
+            }
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                ui.label("Wander Impact");
+                ui.add(
+                    egui::DragValue::new(&mut options.wander_impact)
+                        .speed(0.001)
+                        .clamp_range(0.0..=0.5),
+                );
+            });
+
+            ui.add_enabled_ui(options.wander_impact > 0.0, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label("Wander Radius");
+                    ui.add(
+                        egui::DragValue::new(&mut options.wander_radius)
+                            .speed(0.01)
+                            .clamp_range(0.0..=5.0),
+                    );
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Wander Rate");
+                    ui.add(
+                        egui::DragValue::new(&mut options.wander_rate)
+                            .speed(0.001)
+                            .clamp_range(0.0..=1.0),
+                    );
+                });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Heading Noise");
+                ui.add(
+                    egui::DragValue::new(&mut options.noise)
+                        .speed(0.1)
+                        .suffix("°")
+                        .clamp_range(0.0..=180.0),
+                );
+            });
+
+            ui.separator();
+            leader_ui::leader_ui(ui, &mut options);
+
             ui.separator();
             ui.checkbox(&mut options.speed_limit, "Speed Limit");
 
@@ -269,44 +643,150 @@ fn cgol_gui(
                 ui.add(egui::DragValue::new(&mut options.spawn_amount).clamp_range(1..=1000));
 
                 if ui.button("Spawn").clicked() {
-                    let mesh: Mesh2dHandle = meshes
-                        .add(Mesh::from(shape::RegularPolygon::new(0.5, 3)))
-                        .into();
-
-                    for _ in 0..options.spawn_amount {
-                        spawn_boid(&mut commands, &mut materials, &options, mesh.clone());
-                    }
+                    events.push(SimEvent::spawn(options.spawn_amount as u32, &options));
                 }
             });
 
+            formation_ui(ui, &mut options);
+
             ui.label(format!("Boid Count: {}", state.boid_count));
 
             ui.separator();
-            ui.checkbox(&mut options.migration, "Migration");
-
             ui.horizontal(|ui| {
-                ui.label("Migration Speed");
-                ui.add(egui::DragValue::new(&mut options.migration_speed).clamp_range(-5..=5));
+                ui.label("Brush");
+                egui::ComboBox::from_id_source("brush_tool")
+                    .selected_text(brush.tool.name())
+                    .show_ui(ui, |ui| {
+                        for tool in BrushTool::ALL {
+                            ui.selectable_value(&mut brush.tool, tool, tool.name());
+                        }
+                    });
             });
 
-            if ui.button("Reset Migration").clicked() {
-                state.offset = 0;
+            if brush.tool == BrushTool::Goal {
+                goal_ui::goal_ui(ui, &mut brush.goal);
             }
 
-            ui.label(format!("Migration: {}", state.offset));
+            if matches!(brush.tool, BrushTool::Spawn | BrushTool::Erase) {
+                ui.horizontal(|ui| {
+                    ui.label("Brush Radius");
+                    ui.add(
+                        egui::DragValue::new(&mut brush.radius)
+                            .speed(0.1)
+                            .clamp_range(0.5..=100.0),
+                    );
+                });
+            }
+
+            if brush.tool == BrushTool::Spawn {
+                ui.horizontal(|ui| {
+                    ui.label("Brush Density");
+                    ui.add(
+                        egui::DragValue::new(&mut brush.density)
+                            .speed(0.005)
+                            .clamp_range(0.005..=2.0),
+                    );
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Heading");
+                    egui::ComboBox::from_id_source("brush_heading")
+                        .selected_text(brush.heading.name())
+                        .show_ui(ui, |ui| {
+                            for heading in BrushHeading::ALL {
+                                ui.selectable_value(&mut brush.heading, heading, heading.name());
+                            }
+                        });
+                });
+
+                if brush.heading == BrushHeading::Fixed {
+                    ui.horizontal(|ui| {
+                        ui.label("Fixed Heading");
+                        ui.add(
+                            egui::DragValue::new(&mut brush.fixed_heading)
+                                .suffix("°")
+                                .clamp_range(0.0..=360.0),
+                        );
+                    });
+                }
+
+                ui.horizontal(|ui| {
+                    ui.label("Species");
+                    ui.add(
+                        egui::DragValue::new(&mut brush.species).clamp_range(0..=SPECIES_COUNT - 1),
+                    );
+
+                    let color = if brush.species > 0 {
+                        species_color(brush.species)
+                    } else {
+                        let [r, g, b] = options.foreground_color;
+                        Color::rgb(r, g, b)
+                    };
+                    ui.colored_label(egui_color(color), "⏺");
+                });
+            }
+
+            ui.separator();
+            migration::migration_ui(ui, &mut options, &mut state);
 
             ui.separator();
             ui.label("Visual Options");
             ui.checkbox(&mut options.calculate_rotation, "Calculate Rotation");
             ui.checkbox(&mut options.calculate_color, "Calculate Color");
+            ui.checkbox(&mut options.trails, "Trails");
 
             ui.horizontal(|ui| {
-                if ui
-                    .color_edit_button_rgb(&mut options.foreground_color)
-                    .changed()
-                {
-                    state.prev_calculating_color = true;
-                }
+                ui.label("Trail Length");
+                ui.add(egui::DragValue::new(&mut options.trail_length).clamp_range(2..=120));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Trail Width");
+                ui.add(
+                    egui::DragValue::new(&mut options.trail_width)
+                        .speed(0.01)
+                        .clamp_range(0.01..=2.0),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Trail Fade");
+                ui.add(egui::Slider::new(&mut options.trail_fade, 0.0..=1.0));
+            });
+
+            ui.checkbox(&mut options.heatmap, "Density Heatmap");
+
+            ui.horizontal(|ui| {
+                ui.label("Heatmap Cell Size");
+                ui.add(
+                    egui::DragValue::new(&mut options.heatmap_cell_size).clamp_range(1.0..=50.0),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Heatmap Half-Life");
+                ui.add(
+                    egui::DragValue::new(&mut options.heatmap_half_life)
+                        .suffix("s")
+                        .clamp_range(0.5..=600.0),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Heatmap Colors");
+                egui::ComboBox::from_id_source("heatmap_ramp")
+                    .selected_text(options.heatmap_ramp.name())
+                    .show_ui(ui, |ui| {
+                        for ramp in HeatmapRamp::ALL {
+                            ui.selectable_value(&mut options.heatmap_ramp, ramp, ramp.name());
+                        }
+                    });
+            });
+
+            ui.checkbox(&mut options.heatmap_velocity, "Heatmap Mean Direction");
+
+            ui.horizontal(|ui| {
+                ui.color_edit_button_rgb(&mut options.foreground_color);
 
                 if ui
                     .color_edit_button_rgb(&mut options.background_color)
@@ -317,15 +797,118 @@ fn cgol_gui(
                 }
             });
 
+            ui.separator();
+            ui.label("Camera");
+
+            ui.checkbox(&mut options.minimap, "Minimap");
+
+            ui.horizontal(|ui| {
+                ui.label("Follow");
+                egui::ComboBox::from_id_source("follow_target")
+                    .selected_text(camera.follow.name())
+                    .show_ui(ui, |ui| {
+                        for target in FollowTarget::ALL {
+                            ui.selectable_value(&mut camera.follow, target, target.name());
+                        }
+                    });
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Smoothing");
+                ui.add(egui::Slider::new(&mut camera.smoothing, 1.0..=30.0));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Zoom Sensitivity");
+                ui.add(egui::Slider::new(&mut camera.zoom_sensitivity, 0.1..=5.0));
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Pan Speed");
+                ui.add(
+                    egui::DragValue::new(&mut camera.pan_speed)
+                        .speed(10.0)
+                        .clamp_range(100.0..=5000.0),
+                );
+            });
+
+            if ui.button("Key Bindings").clicked() {
+                binding_editor.open = true;
+            }
+
+            ui.separator();
+            ui.label("Debug Overlays");
+            ui.checkbox(&mut options.debug_border, "Border");
+            ui.checkbox(&mut options.debug_ranges, "Perception Ranges");
+            ui.checkbox(&mut options.debug_neighbors, "Used Neighbors");
+            ui.checkbox(&mut options.debug_forces, "Forces");
+            ui.checkbox(&mut options.debug_only_selected, "Only Selected");
+
+            ui.horizontal(|ui| {
+                ui.label("Force Scale");
+                ui.add(
+                    egui::DragValue::new(&mut options.debug_force_scale).clamp_range(1.0..=1000.0),
+                );
+            });
+
+            ui.horizontal(|ui| {
+                ui.label("Line Width");
+                ui.add(
+                    egui::DragValue::new(&mut options.debug_line_width)
+                        .speed(0.01)
+                        .clamp_range(0.01..=2.0),
+                );
+            });
+
+            if options.debug_forces {
+                for (name, color) in FORCE_LEGEND {
+                    ui.colored_label(egui_color(color), name);
+                }
+            }
+
             ui.separator();
             ui.hyperlink_to("source code", "https://github.com/CatDevz/BadBoids");
         });
 }
 
-fn handle_play_pause(keyboard_input: Res<Input<KeyCode>>, mut options: ResMut<Options>) {
-    if keyboard_input.just_pressed(KeyCode::Space) {
+/// Converts a Bevy color to the one egui uses.
+fn egui_color(color: Color) -> egui::Color32 {
+    let [r, g, b, a] = color.as_rgba_f32();
+    egui::Color32::from_rgba_unmultiplied(
+        (r * 255.0) as u8,
+        (g * 255.0) as u8,
+        (b * 255.0) as u8,
+        (a * 255.0) as u8,
+    )
+}
+
+fn handle_time_controls(
+    actions: Actions,
+    mut options: ResMut<Options>,
+    mut state: ResMut<State>,
+    mut events: ResMut<SimEvents>,
+) {
+    if actions.just_pressed(Action::TogglePause) {
         options.paused = !options.paused
     }
+
+    // Stepping always pauses so the tick can be inspected afterwards
+    if actions.just_pressed(Action::Step) {
+        options.paused = true;
+        state.pending_steps += 1;
+    }
+
+    if actions.just_pressed(Action::FastForward) {
+        options.fast_forward = !options.fast_forward
+    }
+
+    if actions.just_pressed(Action::Spawn) {
+        events.push(SimEvent::spawn(options.spawn_amount as u32, &options));
+    }
+
+    if actions.just_pressed(Action::RemoveAttractor) && state.attractor.is_some() {
+        events.push(SimEvent::MoveAttractor(None));
+    }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
@@ -334,8 +917,57 @@ enum Stage {
     Playing,
 }
 
+/// Data structure used to find the boids within the visibility range of another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum NeighborBackend {
+    KdTree,
+    Grid,
+}
+
+impl NeighborBackend {
+    const ALL: [Self; 2] = [Self::KdTree, Self::Grid];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::KdTree => "KD-Tree",
+            Self::Grid => "Grid",
+        }
+    }
+}
+
+/// Storage the boids are ticked from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum SimulationCore {
+    /// One `Boid` and `Transform` per entity, neighbors found with the [`NeighborBackend`]
+    Ecs,
+    /// Contiguous arrays with vectorized force accumulation, see [`SoaFlock`]
+    Soa,
+}
+
+impl SimulationCore {
+    const ALL: [Self; 2] = [Self::Ecs, Self::Soa];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ecs => "ECS",
+            Self::Soa => "SoA",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 struct Options {
     paused: bool,
+    step_amount: u32,
+    fast_forward: bool,
+    fast_forward_ticks: u32,
+
+    history: bool,
+    history_seconds: u32,
+
+    seed: u64,
+    simulation_core: SimulationCore,
+    neighbor_backend: NeighborBackend,
     visibility_range: f32,
     accuracy: u32,
 
@@ -353,31 +985,124 @@ struct Options {
     border_size: i32,
     border_impact: f32,
 
+    attractor_impact: f32,
+
+    wander_impact: f32,
+    /// Radius of the wander circle relative to its distance ahead of the boid
+    wander_radius: f32,
+    /// New wander directions per tick
+    wander_rate: f32,
+    /// Largest angle in degrees headings are randomly turned by every tick
+    noise: f32,
+
+    /// Drift every boid is carried along by
+    wind: [f32; 2],
+    flow_pattern: FlowPattern,
+    flow_strength: f32,
+    /// Size of the eddies of the flow field in units
+    flow_scale: f32,
+    /// Pattern cycles per second of simulated time
+    flow_evolution: f32,
+    flow_arrows: bool,
+    flow_arrow_spacing: f32,
+
     speed_limit: bool,
     min_speed: f32,
     max_speed: f32,
 
+    /// Number of boids the world starts out with
+    initial_amount: i32,
     spawn_amount: i32,
+    formation: Formation,
+    spawn_speed: f32,
+    /// Number of boids of every spawn that are made leaders
+    spawn_leaders: u32,
+
+    /// How many ordinary flockmates a leader counts as
+    leader_weight: f32,
+    leader_impact: f32,
+    leader_control: LeaderControl,
 
     migration: bool,
     migration_speed: i32,
+    migration_route: MigrationRoute,
+    /// Direction in degrees the border travels in along [`MigrationRoute::Heading`]
+    migration_heading: f32,
+    /// Points the border center travels through along [`MigrationRoute::Waypoints`]
+    migration_waypoints: Vec<[f32; 2]>,
+    /// Whether waypoint routes go back to their first waypoint and start over
+    migration_loop: bool,
 
     calculate_rotation: bool,
     calculate_color: bool,
+    trails: bool,
+    /// Number of ticks a trail reaches back
+    trail_length: u32,
+    trail_width: f32,
+    /// How transparent the oldest end of a trail is, from 0 for not at all to 1 for invisible
+    trail_fade: f32,
+    heatmap: bool,
+    heatmap_cell_size: f32,
+    /// Seconds after which a visit counts half as much towards the density
+    heatmap_half_life: f32,
+    heatmap_ramp: HeatmapRamp,
+    heatmap_velocity: bool,
+    minimap: bool,
+    debug_border: bool,
+    debug_ranges: bool,
+    debug_neighbors: bool,
+    debug_forces: bool,
+    /// Limits the overlays to the boid selected by clicking on it
+    debug_only_selected: bool,
+    /// Forces are only a fraction of a unit, so their arrows are scaled up by this much
+    debug_force_scale: f32,
+    debug_line_width: f32,
     foreground_color: [f32; 3],
     background_color: [f32; 3],
 }
 
 struct State {
     boid_count: u32,
-    prev_calculating_color: bool,
-    offset: i32,
+    /// Center of the border, moved by migration
+    border_center: Vec2,
+    /// How far the border has travelled along the migration route
+    migration_distance: f32,
+
+    tick: u64,
+    pending_steps: u32,
+    tps_ticks: u32,
+    tps_window_start: f64,
+    ticks_per_second: f32,
+
+    next_boid_id: u32,
+    /// Point every boid steers towards, moved with a gamepad
+    attractor: Option<Vec2>,
+    goals: Vec<Goal>,
+    /// Direction the leaders are steered in from the keyboard
+    leader_heading: Option<Vec2>,
+}
+
+/// Random number generator used by the simulation, seeded so that runs can be replayed.
+struct SimRng(StdRng);
+
+impl SimRng {
+    fn new(seed: u64) -> Self {
+        Self(StdRng::seed_from_u64(seed))
+    }
 }
 
 impl Default for Options {
     fn default() -> Self {
         Self {
             paused: true,
+            step_amount: 10,
+            fast_forward: false,
+            fast_forward_ticks: 5,
+            history: false,
+            history_seconds: 10,
+            seed: rand::random(),
+            simulation_core: SimulationCore::Ecs,
+            neighbor_backend: NeighborBackend::KdTree,
             visibility_range: 10.0,
             accuracy: 100,
             separation: true,
@@ -390,16 +1115,56 @@ impl Default for Options {
             border: true,
             border_size: 50,
             border_impact: 0.02,
+            attractor_impact: 0.01,
+            wander_impact: 0.0,
+            wander_radius: 0.5,
+            wander_rate: 0.02,
+            noise: 0.0,
+            wind: [0.0, 0.0],
+            flow_pattern: FlowPattern::None,
+            flow_strength: 0.05,
+            flow_scale: 50.0,
+            flow_evolution: 0.05,
+            flow_arrows: false,
+            flow_arrow_spacing: 5.0,
             speed_limit: true,
             min_speed: 0.3,
             max_speed: 0.2,
+            initial_amount: 100,
             spawn_amount: 100,
+            formation: Formation::Square,
+            spawn_speed: 0.2,
+            spawn_leaders: 0,
+            leader_weight: 5.0,
+            leader_impact: 0.02,
+            leader_control: LeaderControl::Free,
             calculate_rotation: true,
             calculate_color: true,
+            trails: false,
+            trail_length: 30,
+            trail_width: 0.2,
+            trail_fade: 1.0,
+            heatmap: false,
+            heatmap_cell_size: 5.0,
+            heatmap_half_life: 10.0,
+            heatmap_ramp: HeatmapRamp::Heat,
+            heatmap_velocity: false,
+            minimap: true,
+            debug_border: false,
+            debug_ranges: false,
+            debug_neighbors: false,
+            debug_forces: false,
+            debug_only_selected: false,
+            debug_force_scale: 100.0,
+            debug_line_width: 0.1,
             foreground_color: [0.0, 1.0, 0.0915],
             background_color: [0.0, 0.0, 0.0],
             migration: false,
             migration_speed: 1,
+            migration_route: MigrationRoute::Heading,
+            migration_heading: 0.0,
+            migration_waypoints: vec![[0.0, 0.0], [200.0, 100.0], [400.0, -50.0], [600.0, 0.0]],
+            migration_loop: false,
         }
     }
 }
@@ -408,8 +1173,76 @@ impl Default for State {
     fn default() -> Self {
         Self {
             boid_count: 0,
-            prev_calculating_color: true,
-            offset: 0,
+            border_center: Vec2::ZERO,
+            migration_distance: 0.0,
+            tick: 0,
+            pending_steps: 0,
+            tps_ticks: 0,
+            tps_window_start: 0.0,
+            ticks_per_second: 0.0,
+            next_boid_id: 0,
+            attractor: None,
+            goals: Vec::new(),
+            leader_heading: None,
+        }
+    }
+}
+
+impl Options {
+    /// Takes the options from a recording while keeping the local playback controls.
+    fn apply_recorded(&mut self, recorded: &Options) {
+        *self = Options {
+            paused: self.paused,
+            step_amount: self.step_amount,
+            fast_forward: self.fast_forward,
+            fast_forward_ticks: self.fast_forward_ticks,
+            history: self.history,
+            history_seconds: self.history_seconds,
+            ..recorded.clone()
+        };
+    }
+
+    /// Copy with everything that doesn't change the outcome of a run reset to the defaults, so
+    /// that changes to it don't have to be recorded. Spawn settings only matter through the
+    /// spawn events, which carry them.
+    fn simulation_only(&self) -> Options {
+        let defaults = Options::default();
+        Options {
+            paused: defaults.paused,
+            step_amount: defaults.step_amount,
+            fast_forward: defaults.fast_forward,
+            fast_forward_ticks: defaults.fast_forward_ticks,
+            history: defaults.history,
+            history_seconds: defaults.history_seconds,
+            flow_arrows: defaults.flow_arrows,
+            flow_arrow_spacing: defaults.flow_arrow_spacing,
+            initial_amount: defaults.initial_amount,
+            spawn_amount: defaults.spawn_amount,
+            formation: defaults.formation,
+            spawn_speed: defaults.spawn_speed,
+            spawn_leaders: defaults.spawn_leaders,
+            calculate_rotation: defaults.calculate_rotation,
+            calculate_color: defaults.calculate_color,
+            trails: defaults.trails,
+            trail_length: defaults.trail_length,
+            trail_width: defaults.trail_width,
+            trail_fade: defaults.trail_fade,
+            heatmap: defaults.heatmap,
+            heatmap_cell_size: defaults.heatmap_cell_size,
+            heatmap_half_life: defaults.heatmap_half_life,
+            heatmap_ramp: defaults.heatmap_ramp,
+            heatmap_velocity: defaults.heatmap_velocity,
+            minimap: defaults.minimap,
+            debug_border: defaults.debug_border,
+            debug_ranges: defaults.debug_ranges,
+            debug_neighbors: defaults.debug_neighbors,
+            debug_forces: defaults.debug_forces,
+            debug_only_selected: defaults.debug_only_selected,
+            debug_force_scale: defaults.debug_force_scale,
+            debug_line_width: defaults.debug_line_width,
+            foreground_color: defaults.foreground_color,
+            background_color: defaults.background_color,
+            ..self.clone()
         }
     }
 }
@@ -431,74 +1264,265 @@ struct BoidBundle {
 
 #[derive(Debug, Component, Default, Clone)]
 struct Boid {
+    id: u32,
     flock_size: u32,
     vx: f32,
     vy: f32,
+    /// Boids only flock with their own species and keep apart from the others
+    species: u8,
+    /// Flockmates follow leaders more closely
+    leader: bool,
 }
 
-fn spawn_boid(
+struct BoidMesh(Mesh2dHandle);
+
+/// Spawns a boid with the next id. Its mesh and material are attached by [`init_boid_visuals`]
+/// so the simulation can also run without rendering.
+fn spawn_boid_at(
     commands: &mut Commands,
-    materials: &mut ResMut<Assets<ColorMaterial>>,
-    options: &Options,
-    mesh: Mesh2dHandle,
+    state: &mut State,
+    pos: Vec2,
+    vel: Vec2,
+    species: u8,
+    leader: bool,
 ) {
-    let border_size = options.border_size as f32;
+    let id = state.next_boid_id;
+    state.next_boid_id += 1;
+
     commands.spawn_bundle(BoidBundle {
-        mesh,
-        transform: Transform::default()
-            .with_translation(Vec3 {
-                x: rand::random::<f32>() * border_size * 2.0 - border_size,
-                y: rand::random::<f32>() * border_size * 2.0 - border_size,
-                ..Default::default()
-            })
-            .with_scale(Vec3 {
-                x: 0.7,
-                y: 1.1,
-                z: 1.0,
-            }),
-        material: {
-            let [r, g, b] = options.foreground_color;
-            materials.add(ColorMaterial::from(Color::rgb(r, g, b)))
+        boid: Boid {
+            id,
+            vx: vel.x,
+            vy: vel.y,
+            species,
+            leader,
+            ..Default::default()
         },
+        transform: Transform::default()
+            .with_translation(pos.extend(0.0))
+            .with_scale(BOID_SCALE),
         ..Default::default()
     });
 }
 
-fn calculate_boid_color(
-    query: Query<(&Boid, &Handle<ColorMaterial>)>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-    mut state: ResMut<State>,
-    options: Res<Options>,
+fn init_boid_visuals(
+    mut query: Query<
+        (
+            &Boid,
+            &mut Mesh2dHandle,
+            &mut Handle<ColorMaterial>,
+            &mut Visibility,
+        ),
+        Added<Boid>,
+    >,
+    boid_mesh: Res<BoidMesh>,
+    palette: Res<BoidPalette>,
+    instanced: Res<InstancedRendering>,
 ) {
-    if !options.calculate_color {
-        if state.prev_calculating_color {
-            state.prev_calculating_color = false;
-
-            let [r, g, b] = options.foreground_color;
-            for (_, mat_handle) in query.iter() {
-                let Some(mut material) = materials.get_mut(mat_handle) else {
-                    continue;
-                };
+    for (boid, mut mesh, mut material, mut visibility) in query.iter_mut() {
+        // Instanced boids are drawn from a single buffer, their entities are only for the logic
+        if instanced.0 {
+            visibility.is_visible = false;
+            continue;
+        }
+
+        *mesh = boid_mesh.0.clone();
+        *material = palette.species_material(boid.species).clone();
+    }
+}
+
+/// Applies an event to the simulation, returning whether it spawned or despawned boids.
+fn apply_sim_event(
+    event: SimEvent,
+    commands: &mut Commands,
+    boids: &mut Query<(Entity, &mut Boid, &mut Transform)>,
+    options: &mut Options,
+    state: &mut State,
+    flow_grid: &mut FlowGrid,
+    rng: &mut SimRng,
+) -> bool {
+    match event {
+        SimEvent::Spawn {
+            count,
+            formation,
+            speed,
+            leaders,
+        } => {
+            let extent = options.border_size as f32;
+            let placed = formation.place(&mut rng.0, count, extent, speed);
+            for (i, (pos, vel)) in placed.into_iter().enumerate() {
+                spawn_boid_at(commands, state, pos, vel, 0, (i as u32) < leaders);
+            }
+
+            return true;
+        }
+        SimEvent::SetVelocity { id, vx, vy } => {
+            for (_, mut boid, _) in boids.iter_mut() {
+                if boid.id == id {
+                    boid.vx = vx;
+                    boid.vy = vy;
+                }
+            }
+        }
+        SimEvent::Paint {
+            center,
+            radius,
+            count,
+            heading,
+            species,
+        } => {
+            for _ in 0..count {
+                // Taking the square root spreads the boids evenly over the circle
+                let angle = rng.0.gen::<f32>() * TAU;
+                let distance = radius * rng.0.gen::<f32>().sqrt();
+                let pos = Vec2::from(center) + Vec2::new(angle.cos(), angle.sin()) * distance;
+
+                let heading = heading.unwrap_or_else(|| rng.0.gen::<f32>() * TAU);
+                let vel = Vec2::new(heading.cos(), heading.sin()) * options.max_speed;
+                spawn_boid_at(commands, state, pos, vel, species, false);
+            }
+
+            return true;
+        }
+        SimEvent::Erase { center, radius } => {
+            let center = Vec2::from(center);
+            for (entity, _, transform) in boids.iter() {
+                if transform.translation.truncate().distance(center) <= radius {
+                    commands.entity(entity).despawn();
+                }
+            }
+
+            return true;
+        }
+        SimEvent::Despawn { id } => {
+            for (entity, boid, _) in boids.iter() {
+                if boid.id == id {
+                    commands.entity(entity).despawn();
+                }
+            }
 
-                material.color = Color::rgb(r, g, b);
+            return true;
+        }
+        SimEvent::SetLeader { id, leader } => {
+            for (_, mut boid, _) in boids.iter_mut() {
+                if boid.id == id {
+                    boid.leader = leader;
+                }
+            }
+        }
+        SimEvent::MoveAttractor(attractor) => state.attractor = attractor.map(Vec2::from),
+        SimEvent::SteerLeaders(heading) => state.leader_heading = heading.map(Vec2::from),
+        SimEvent::PlaceGoal(goal) => state.goals.push(goal),
+        SimEvent::RemoveGoal(index) => {
+            if index < state.goals.len() {
+                state.goals.remove(index);
             }
         }
+        SimEvent::LoadFlowGrid(grid) => flow_grid.0 = grid,
+        SimEvent::SetOptions(recorded) => options.apply_recorded(&recorded),
+        // Pausing doesn't change the outcome of a run, it's only logged
+        SimEvent::Pause(_) => {}
+    }
+
+    false
+}
+
+/// Number of distinct flock colors, the hue grows by 5 degrees per flockmate up to 140.
+const FLOCK_COLOR_STEPS: u32 = 29;
+
+/// Color of a boid based on the number of boids in its flock.
+fn flock_color(flock_size: u32) -> Color {
+    Color::hsl(clamp(flock_size * 5, 0, 140) as f32, 1.0, 0.5)
+}
+
+/// Number of species boids can be painted as, 0 is the one spawned by default.
+const SPECIES_COUNT: u8 = 4;
+
+/// Color of every species but the default one, which is drawn in the foreground color.
+fn species_color(species: u8) -> Color {
+    Color::hsl((100.0 + species as f32 * 100.0) % 360.0, 0.8, 0.6)
+}
+
+/// Color a boid is drawn with, the same one [`calculate_boid_color`] picks its material by.
+fn boid_color(boid: &Boid, options: &Options) -> Color {
+    if options.calculate_color {
+        flock_color(boid.flock_size)
+    } else if boid.species > 0 {
+        species_color(boid.species)
+    } else {
+        let [r, g, b] = options.foreground_color;
+        Color::rgb(r, g, b)
+    }
+}
+
+/// Materials shared by every boid. Recoloring a boid swaps its handle rather than changing an
+/// asset, so boids keep being batched together and no material has to be uploaded again.
+struct BoidPalette {
+    foreground: Handle<ColorMaterial>,
+    flock_colors: Vec<Handle<ColorMaterial>>,
+    species_colors: Vec<Handle<ColorMaterial>>,
+}
+
+impl BoidPalette {
+    fn new(materials: &mut Assets<ColorMaterial>, [r, g, b]: [f32; 3]) -> Self {
+        Self {
+            foreground: materials.add(ColorMaterial::from(Color::rgb(r, g, b))),
+            flock_colors: (0..FLOCK_COLOR_STEPS)
+                .map(|it| materials.add(ColorMaterial::from(flock_color(it))))
+                .collect(),
+            species_colors: (1..SPECIES_COUNT)
+                .map(|it| materials.add(ColorMaterial::from(species_color(it))))
+                .collect(),
+        }
+    }
+
+    fn species_material(&self, species: u8) -> &Handle<ColorMaterial> {
+        match species {
+            0 => &self.foreground,
+            _ => &self.species_colors[(species as usize - 1).min(self.species_colors.len() - 1)],
+        }
+    }
+
+    fn flock_material(&self, flock_size: u32) -> &Handle<ColorMaterial> {
+        &self.flock_colors[(flock_size as usize).min(self.flock_colors.len() - 1)]
+    }
+}
 
+fn calculate_boid_color(
+    mut query: Query<(&Boid, &mut Handle<ColorMaterial>)>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    palette: Res<BoidPalette>,
+    options: Res<Options>,
+    instanced: Res<InstancedRendering>,
+) {
+    // The instanced path picks its colors when packing the instances
+    if instanced.0 {
         return;
     }
 
-    if !state.prev_calculating_color {
-        state.prev_calculating_color = true;
+    // Keeping the shared foreground material in sync with the color picker
+    let [r, g, b] = options.foreground_color;
+    let foreground = Color::rgb(r, g, b);
+    if materials
+        .get(&palette.foreground)
+        .map_or(false, |it| it.color != foreground)
+    {
+        if let Some(material) = materials.get_mut(&palette.foreground) {
+            material.color = foreground;
+        }
     }
 
-    for (boid, mat_handle) in query.iter() {
-        // Get the material using the handle
-        let Some(mut material) = materials.get_mut(mat_handle) else {
-            continue;
+    for (boid, mut mat_handle) in query.iter_mut() {
+        // Picking the material based on the number of boids in its flock or its species
+        let material = if options.calculate_color {
+            palette.flock_material(boid.flock_size)
+        } else {
+            palette.species_material(boid.species)
         };
 
-        // Set the material color based on the number of boids in its flock
-        material.color = Color::hsl(clamp(boid.flock_size * 5, 0, 140) as f32, 1.0, 0.5);
+        if *mat_handle != *material {
+            *mat_handle = material.clone();
+        }
     }
 }
 
@@ -513,142 +1537,219 @@ fn calculate_boid_rotation(mut query: Query<(&Boid, &mut Transform)>, options: R
     }
 }
 
-fn update_stats(mut state: ResMut<State>, query: Query<&Boid>) {
+fn update_stats(mut state: ResMut<State>, query: Query<&Boid>, time: Res<Time>) {
     state.boid_count = query.iter().len() as u32;
-}
 
-fn migrate(options: Res<Options>, mut state: ResMut<State>) {
-    if options.migration && !options.paused {
-        state.offset += options.migration_speed;
+    // Averaging the tick rate over roughly a second so the readout stays legible
+    let now = time.seconds_since_startup();
+    let elapsed = now - state.tps_window_start;
+    if elapsed >= 1.0 {
+        state.ticks_per_second = (state.tps_ticks as f64 / elapsed) as f32;
+        state.tps_ticks = 0;
+        state.tps_window_start = now;
     }
 }
 
 fn tick_boids(
+    mut commands: Commands,
     mut query: Query<(Entity, &mut Boid, &mut Transform)>,
-    options: Res<Options>,
-    state: Res<State>,
-    tree: Res<BoidNNTree>,
+    mut options: ResMut<Options>,
+    mut state: ResMut<State>,
+    mut flow_grid: ResMut<FlowGrid>,
+    mut history: ResMut<History>,
+    mut tree: ResMut<BoidNNTree>,
+    mut last_grid: ResMut<LastGrid>,
+    mut soa: ResMut<SoaFlock>,
+    mut forces: ResMut<BoidForces>,
+    mut rng: ResMut<SimRng>,
+    mut events: ResMut<SimEvents>,
+    mut recorder: ResMut<Recorder>,
+    mut replay: ResMut<Replay>,
 ) {
-    if options.paused {
+    // Starting a replay resets the world to the recorded snapshot
+    if let Some(snapshot) = replay.take_start() {
+        for (entity, _, _) in query.iter() {
+            commands.entity(entity).despawn();
+        }
+
+        snapshot.restore(
+            &mut commands,
+            &mut options,
+            &mut state,
+            &mut flow_grid,
+            &mut rng,
+        );
+        options.paused = false;
+        events.clear();
+        history.clear();
         return;
     }
 
-    let boid_iter = query.iter();
-    let mut updated_boids = Vec::<(Entity, Boid, Transform)>::with_capacity(boid_iter.len());
-
-    for (entity, boid, transform) in boid_iter {
-        let mut boid = boid.clone();
-        let mut transform = *transform;
-
-        // Setting some basic variables
-        let pos = transform.translation;
-
-        let mut close_dx = 0.0;
-        let mut close_dy = 0.0;
-        let mut flock_vx_sum = 0.0;
-        let mut flock_vy_sum = 0.0;
-        let mut flock_x_sum = 0.0;
-        let mut flock_y_sum = 0.0;
-
-        // Getting the flock
-        let flock = tree.within_distance(pos, options.visibility_range);
-        let flock_size = flock.len() as u32;
-
-        // Copying some debug info
-        boid.flock_size = flock_size;
-
-        // Looping through every other boid in the flock
-        let flock = flock
-            .into_iter()
-            .filter_map(|it| Some((it.0, query.get(it.1).ok()?.1)))
-            .collect::<Vec<_>>();
-
-        for (i, (other_pos, other_boid)) in flock.into_iter().enumerate() {
-            if i as u32 > options.accuracy {
-                break;
-            }
-
-            // Getting the distance between our boid and the other
-            let Vec3 { x: dx, y: dy, z: _ } = pos - other_pos;
-
-            // Applying separation if boids are close enough and cohesion if they are far
-            // enough
-            if (dx * dx + dy * dy) < options.separation_range && options.separation {
-                close_dx += dx;
-                close_dy += dy;
-            } else if options.cohesion {
-                flock_x_sum += other_pos.x;
-                flock_y_sum += other_pos.y;
+    // Resuming from a point picked on the timeline, boids spawned after it are discarded
+    if !options.paused || state.pending_steps > 0 {
+        if let Some(frame) = history.resume() {
+            // The recording can't be continued once time has been rewound under it
+            recorder.cancel();
+
+            let alive = frame.entities.iter().copied().collect::<HashSet<_>>();
+            let mut despawned = false;
+            for (entity, _, _) in query.iter() {
+                if !alive.contains(&entity) {
+                    commands.entity(entity).despawn();
+                    despawned = true;
+                }
             }
 
-            // Applying alignment if enabled
-            if options.alignment {
-                flock_vx_sum += other_boid.vx;
-                flock_vy_sum += other_boid.vy;
+            // Waiting for the despawns to be applied before ticking again
+            if despawned {
+                return;
             }
         }
+    }
 
-        if flock_size > 0 {
-            let flock_vx_avrg = flock_vx_sum / flock_size as f32;
-            let flock_vy_avrg = flock_vy_sum / flock_size as f32;
-            boid.vx += (flock_vx_avrg - boid.vx) * options.alignment_impact;
-            boid.vy += (flock_vy_avrg - boid.vy) * options.alignment_impact;
+    recorder.update(
+        query.iter().map(|(_, boid, transform)| (boid, transform)),
+        &options,
+        &state,
+        &flow_grid,
+        &mut rng,
+    );
 
-            let flock_x_avrg = flock_x_sum / flock_size as f32;
-            let flock_y_avrg = flock_y_sum / flock_size as f32;
-            boid.vx += (flock_x_avrg - pos.x) * 0.0005;
-            boid.vy += (flock_y_avrg - pos.y) * 0.0005;
+    let history_capacity =
+        (options.history_seconds as f64 * TICK_RATE / HISTORY_INTERVAL as f64) as usize;
+    let mut ticks = if options.paused {
+        state.pending_steps
+    } else if options.fast_forward {
+        options.fast_forward_ticks
+    } else {
+        1
+    };
+
+    // A replay stops on the tick its recording was stopped on, and only the recorded events are
+    // applied so that it can't diverge from the recording
+    if let Some(remaining) = replay.remaining_ticks(state.tick) {
+        let clamped = (ticks as u64).min(remaining) as u32;
+        // Steps past the end of the recording are dropped instead of staying pending forever
+        if options.paused {
+            state.pending_steps -= ticks - clamped;
         }
 
-        boid.vx += close_dx * options.separation_impact;
-        boid.vy += close_dy * options.separation_impact;
-
-        // Bounding boxes
-        if options.border {
-            let size = options.border_size as f32;
-            if transform.translation.x > (size + state.offset as f32) {
-                boid.vx -= options.border_impact;
-            }
-
-            if transform.translation.x < -(size - state.offset as f32) {
-                boid.vx += options.border_impact;
-            }
+        ticks = clamped;
+        events.clear();
+    }
 
-            if transform.translation.y > size {
-                boid.vy -= options.border_impact;
-            }
+    loop {
+        // Recorded events go through the same queue as the ones coming from the user
+        replay.enqueue_due(state.tick, &mut events);
+        let mut changed_boids = false;
+        for event in events.drain() {
+            recorder.record(state.tick, &event);
+            changed_boids |= apply_sim_event(
+                event,
+                &mut commands,
+                &mut query,
+                &mut options,
+                &mut state,
+                &mut flow_grid,
+                &mut rng,
+            );
+        }
 
-            if transform.translation.y < -size {
-                boid.vy += options.border_impact;
-            }
+        // Boids spawned or despawned by the events only exist or are gone once the commands are
+        // applied, so the next tick has to wait until then
+        if changed_boids {
+            return;
         }
 
-        // Speed limits
-        if options.speed_limit {
-            let speed = sqrt((boid.vx * boid.vx + boid.vy * boid.vy) as f64) as f32;
+        if ticks == 0 {
+            break;
+        }
 
-            if speed < options.min_speed {
-                boid.vx = (boid.vx / speed) * options.min_speed;
-                boid.vy = (boid.vy / speed) * options.min_speed;
-            }
+        ticks -= 1;
+        if options.paused {
+            state.pending_steps -= 1;
+        }
 
-            if speed > options.max_speed {
-                boid.vx = (boid.vx / speed) * options.max_speed;
-                boid.vy = (boid.vy / speed) * options.max_speed;
-            }
+        if options.migration && state.tick % MIGRATION_INTERVAL == 0 {
+            migration::advance(&options, &mut state);
         }
 
-        // Calculating the new position based on the velocity of the boid
-        transform.translation.x += boid.vx;
-        transform.translation.y += boid.vy;
+        let updated_boids = step_boids(
+            &mut query,
+            &options,
+            &state,
+            &flow_grid,
+            &mut tree,
+            &mut last_grid,
+            &mut soa,
+            &mut forces,
+        );
+        goal_ui::consume_goals(&mut state.goals, &updated_boids);
+        state.tick += 1;
+        state.tps_ticks += 1;
+
+        if options.history && state.tick % HISTORY_INTERVAL == 0 {
+            let frame = HistoryFrame::new(&state, &updated_boids, history.last());
+            history.push(frame, history_capacity);
+        }
+    }
 
-        // Adding the updated boid
-        updated_boids.push((entity, boid, transform));
+    if replay.is_done(state.tick) {
+        replay.finish(crate::replay::checksum(
+            query.iter().map(|(_, boid, transform)| (boid, transform)),
+        ));
+        options.paused = true;
     }
+}
+
+/// Advances every boid by a single simulation tick, returning their new state.
+///
+/// The new state of every boid is computed from a snapshot of the previous tick and only written
+/// back once all of them are done, so boids can be updated in any order, or in parallel, without
+/// changing the result.
+fn step_boids(
+    query: &mut Query<(Entity, &mut Boid, &mut Transform)>,
+    options: &Options,
+    state: &State,
+    flow_grid: &FlowGrid,
+    tree: &mut BoidNNTree,
+    last_grid: &mut LastGrid,
+    soa: &mut SoaFlock,
+    forces: &mut BoidForces,
+) -> Vec<(Entity, Boid, Transform)> {
+    // Boids are processed in id order and the neighbor search is rebuilt from the current
+    // positions, so a tick only depends on the simulation state and not on the order of
+    // entities in the world
+    let mut boids = query
+        .iter()
+        .map(|(entity, boid, transform)| (entity, boid.clone(), *transform))
+        .collect::<Vec<_>>();
+    boids.sort_unstable_by_key(|it| it.1.id);
+
+    let params = sim_params(options, state, flow_grid);
+    let (updated_boids, grid) = match options.simulation_core {
+        SimulationCore::Ecs => update_boids_ecs(&boids, tree, options, &params, forces),
+        SimulationCore::Soa => {
+            let updated_boids = update_boids_soa(&boids, soa, &params);
+
+            // The structure of arrays core doesn't break the velocity change down, so it's
+            // worked out again for the few boids that show it
+            let recorded = boids.iter().any(|it| forces.records(it.0, options));
+            let grid = recorded.then(|| snapshot_grid(&boids, options));
+            let recorded = grid.as_ref().map_or_else(Vec::new, |grid| {
+                record_forces(&boids, grid, options, &params, forces)
+            });
+            forces.set(recorded.into_iter());
+            (updated_boids, grid)
+        }
+    };
+
+    // The other neighbor searches leave no grid behind, so one is only built for the heatmap
+    last_grid.0 = grid.or_else(|| options.heatmap.then(|| snapshot_grid(&boids, options)));
 
     // Looping through every boid and applying it to its actual entity
-    for (entity, updated_boid, updated_transform) in updated_boids.into_iter() {
-        let Ok((_, mut boid, mut transform)) = query.get_mut(entity) else { continue; };
+    for (entity, updated_boid, updated_transform) in updated_boids.iter() {
+        let Ok((_, mut boid, mut transform)) = query.get_mut(*entity) else { continue; };
 
         // Updating the boid itself
         boid.flock_size = updated_boid.flock_size;
@@ -660,4 +1761,256 @@ fn tick_boids(
         transform.rotation = updated_transform.rotation;
         transform.scale = updated_transform.scale;
     }
+
+    updated_boids
+}
+
+/// Updates the snapshot with the neighbor search picked in the options, returning the grid the
+/// boids were looked up in when it's the grid search.
+fn update_boids_ecs(
+    boids: &[(Entity, Boid, Transform)],
+    tree: &mut BoidNNTree,
+    options: &Options,
+    params: &SoaParams,
+    forces: &mut BoidForces,
+) -> (Vec<(Entity, Boid, Transform)>, Option<SpatialGrid>) {
+    let neighbors = match options.neighbor_backend {
+        NeighborBackend::KdTree => {
+            tree.recreate(boids.iter().map(|it| (it.2.translation, it.0)).collect());
+            Neighbors::KdTree {
+                tree,
+                indices: boids.iter().enumerate().map(|(i, it)| (it.0, i)).collect(),
+            }
+        }
+        NeighborBackend::Grid => Neighbors::Grid(snapshot_grid(boids, options)),
+    };
+
+    let records = |entity| forces.records(entity, options);
+    let update = |(entity, boid, transform): &(Entity, Boid, Transform)| {
+        let mut boid_forces = records(*entity).then(Forces::default);
+        let (boid, transform) = update_boid(
+            boid,
+            transform,
+            boids,
+            &neighbors,
+            params,
+            boid_forces.as_mut(),
+        );
+        ((*entity, boid, transform), boid_forces)
+    };
+
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    let updated_boids = boids
+        .par_chunk_map(ComputeTaskPool::get(), PARALLEL_CHUNK_SIZE, |chunk| {
+            chunk.iter().map(update).collect::<Vec<_>>()
+        })
+        .into_iter()
+        .flatten();
+
+    #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+    let updated_boids = boids.iter().map(update);
+
+    let (updated_boids, boid_forces): (Vec<_>, Vec<_>) = updated_boids.unzip();
+    forces.set(
+        updated_boids
+            .iter()
+            .zip(boid_forces)
+            .filter_map(|(it, forces)| Some((it.0, forces?))),
+    );
+
+    let grid = match neighbors {
+        Neighbors::Grid(grid) => Some(grid),
+        Neighbors::KdTree { .. } => None,
+    };
+
+    (updated_boids, grid)
+}
+
+/// Grid neighbor search over the boids of the snapshot.
+fn snapshot_grid(boids: &[(Entity, Boid, Transform)], options: &Options) -> SpatialGrid {
+    let entries = boids
+        .iter()
+        .map(|(_, boid, transform)| grid_entry(boid, transform))
+        .collect::<Vec<_>>();
+    SpatialGrid::new(&entries, options.visibility_range)
+}
+
+/// Breaks the velocity change of the boids that record it down with the rules of the ECS core,
+/// which the structure of arrays core applies the same way up to rounding.
+fn record_forces(
+    boids: &[(Entity, Boid, Transform)],
+    grid: &SpatialGrid,
+    options: &Options,
+    params: &SoaParams,
+    forces: &BoidForces,
+) -> Vec<(Entity, Forces)> {
+    boids
+        .iter()
+        .filter(|it| forces.records(it.0, options))
+        .map(|(entity, boid, transform)| {
+            let entry = grid_entry(boid, transform);
+            let mut boid_forces = Forces::default();
+            step_boid(
+                boid.id,
+                &entry,
+                params,
+                |visit| grid.for_each_within(entry.pos, params.visibility_range, visit),
+                Some(&mut boid_forces),
+            );
+            (*entity, boid_forces)
+        })
+        .collect()
+}
+
+/// Updates the snapshot by copying it into the structure of arrays core and back.
+fn update_boids_soa(
+    boids: &[(Entity, Boid, Transform)],
+    soa: &mut SoaFlock,
+    params: &SoaParams,
+) -> Vec<(Entity, Boid, Transform)> {
+    soa.clear();
+    for (_, boid, transform) in boids.iter() {
+        soa.push(
+            transform.translation.truncate(),
+            Vec2::new(boid.vx, boid.vy),
+            boid.species,
+            boid.id,
+            boid.leader,
+        );
+    }
+
+    soa.step(params);
+
+    boids
+        .iter()
+        .enumerate()
+        .map(|(i, (entity, boid, transform))| {
+            let mut boid = boid.clone();
+            boid.flock_size = soa.flock_size[i];
+            boid.vx = soa.vx[i];
+            boid.vy = soa.vy[i];
+
+            let mut transform = *transform;
+            transform.translation.x = soa.px[i];
+            transform.translation.y = soa.py[i];
+            (*entity, boid, transform)
+        })
+        .collect()
+}
+
+/// Flocking rules of the current tick, the same for both cores.
+fn sim_params<'a>(options: &Options, state: &'a State, flow_grid: &'a FlowGrid) -> SoaParams<'a> {
+    SoaParams {
+        visibility_range: options.visibility_range,
+        accuracy: options.accuracy,
+        separation: options.separation,
+        separation_range: options.separation_range,
+        separation_impact: options.separation_impact,
+        alignment: options.alignment,
+        alignment_impact: options.alignment_impact,
+        cohesion: options.cohesion,
+        cohesion_impact: options.cohesion_impact,
+        border: options.border,
+        border_size: options.border_size as f32,
+        border_impact: options.border_impact,
+        border_center: state.border_center,
+        attractor: state.attractor,
+        attractor_impact: options.attractor_impact,
+        goals: &state.goals,
+        leaders: leader_ui::leader_params(options, state),
+        wander: wander_params(options, state),
+        flow: flow_ui::flow_field(options, state, flow_grid),
+        speed_limit: options.speed_limit,
+        min_speed: options.min_speed,
+        max_speed: options.max_speed,
+    }
+}
+
+/// Copy of a boid as the neighbor searches and the flocking rules see it.
+fn grid_entry(boid: &Boid, transform: &Transform) -> GridEntry {
+    GridEntry {
+        pos: transform.translation.truncate(),
+        vel: Vec2::new(boid.vx, boid.vy),
+        species: boid.species,
+        leader: boid.leader,
+    }
+}
+
+/// Boids sorted into the cells of the grid neighbor search on the last tick, before they moved.
+/// Empty when another search was used and the heatmap is off.
+#[derive(Default)]
+struct LastGrid(Option<SpatialGrid>);
+
+/// Neighbor search structure built at the start of a tick.
+enum Neighbors<'a> {
+    /// The tree only knows entities, so they're mapped back to their index in the snapshot
+    KdTree {
+        tree: &'a BoidNNTree,
+        indices: HashMap<Entity, usize>,
+    },
+    Grid(SpatialGrid),
+}
+
+impl Neighbors<'_> {
+    /// Calls `f` for every boid within `radius` of `pos`, the grid already holds velocities
+    /// while the tree results have to be looked up in the snapshot.
+    fn for_each_within(
+        &self,
+        boids: &[(Entity, Boid, Transform)],
+        pos: Vec2,
+        radius: f32,
+        f: &mut dyn FnMut(&GridEntry),
+    ) {
+        match self {
+            Neighbors::Grid(grid) => grid.for_each_within(pos, radius, f),
+            Neighbors::KdTree { tree, indices } => {
+                for (_, other_entity) in tree.within_distance(pos.extend(0.0), radius) {
+                    let Some(&i) = indices.get(&other_entity) else { continue; };
+                    let (_, other_boid, other_transform) = &boids[i];
+                    f(&grid_entry(other_boid, other_transform));
+                }
+            }
+        }
+    }
+}
+
+/// Computes the state of a boid after one tick from the snapshot of the previous tick.
+fn update_boid(
+    boid: &Boid,
+    transform: &Transform,
+    boids: &[(Entity, Boid, Transform)],
+    neighbors: &Neighbors,
+    params: &SoaParams,
+    forces: Option<&mut Forces>,
+) -> (Boid, Transform) {
+    let entry = grid_entry(boid, transform);
+    let stepped = step_boid(
+        boid.id,
+        &entry,
+        params,
+        |visit| neighbors.for_each_within(boids, entry.pos, params.visibility_range, visit),
+        forces,
+    );
+
+    let mut boid = boid.clone();
+    boid.flock_size = stepped.flock_size;
+    boid.vx = stepped.vel.x;
+    boid.vy = stepped.vel.y;
+
+    let mut transform = *transform;
+    transform.translation.x = stepped.pos.x;
+    transform.translation.y = stepped.pos.y;
+    (boid, transform)
+}
+
+/// Random wandering and heading noise on the current tick.
+fn wander_params(options: &Options, state: &State) -> WanderParams {
+    WanderParams {
+        seed: options.seed,
+        tick: state.tick,
+        impact: options.wander_impact,
+        radius: options.wander_radius,
+        rate: options.wander_rate,
+        noise: options.noise.to_radians(),
+    }
 }
@@ -0,0 +1,331 @@
+use bevy::prelude::{
+    Assets, Color, Commands, Component, Local, Mesh, Query, Res, ResMut, Vec2, Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+use bevy_egui::egui;
+use serde::{Deserialize, Serialize};
+
+use crate::debug::circle;
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::{Options, State};
+
+const ROUTE_COLOR: Color = Color::rgba(0.4, 0.8, 1.0, 0.7);
+
+const ROUTE_WIDTH: f32 = 0.4;
+
+/// Way the border travels while migrating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MigrationRoute {
+    /// Straight on in a fixed direction
+    Heading,
+    /// Through a list of points, one after the other
+    Waypoints,
+}
+
+impl MigrationRoute {
+    pub const ALL: [Self; 2] = [Self::Heading, Self::Waypoints];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Heading => "Heading",
+            Self::Waypoints => "Waypoints",
+        }
+    }
+}
+
+/// Segments of the waypoint route, closed back to the first waypoint when it loops.
+fn segments(options: &Options) -> Vec<(Vec2, Vec2)> {
+    let points = options
+        .migration_waypoints
+        .iter()
+        .map(|it| Vec2::from(*it))
+        .collect::<Vec<_>>();
+    let mut segments = points
+        .windows(2)
+        .map(|it| (it[0], it[1]))
+        .collect::<Vec<_>>();
+
+    if options.migration_loop && points.len() > 1 {
+        segments.push((points[points.len() - 1], points[0]));
+    }
+
+    segments
+}
+
+/// Center of the border after travelling `distance` along the route.
+pub fn route_position(options: &Options, distance: f32) -> Vec2 {
+    match options.migration_route {
+        MigrationRoute::Heading => {
+            let angle = options.migration_heading.to_radians();
+            Vec2::new(angle.cos(), angle.sin()) * distance
+        }
+        MigrationRoute::Waypoints => {
+            let Some(first) = options.migration_waypoints.first() else {
+                return Vec2::ZERO;
+            };
+
+            let mut remaining = distance;
+            let mut end = Vec2::from(*first);
+            for (from, to) in segments(options) {
+                let length = from.distance(to);
+                if length > 0.0 && remaining <= length {
+                    return from.lerp(to, remaining / length);
+                }
+
+                remaining -= length;
+                end = to;
+            }
+
+            end
+        }
+    }
+}
+
+/// Moves the border one migration step further along the route. Waypoint routes stop at their
+/// ends unless they loop.
+pub fn advance(options: &Options, state: &mut State) {
+    let mut distance = state.migration_distance + options.migration_speed as f32;
+    if options.migration_route == MigrationRoute::Waypoints {
+        let length = segments(options)
+            .iter()
+            .map(|(from, to)| from.distance(*to))
+            .sum::<f32>();
+
+        distance = if options.migration_loop && length > 0.0 {
+            distance.rem_euclid(length)
+        } else {
+            distance.clamp(0.0, length)
+        };
+    }
+
+    state.migration_distance = distance;
+    state.border_center = route_position(options, distance);
+}
+
+/// Migration settings, the waypoints can be edited, added and removed here.
+pub fn migration_ui(ui: &mut egui::Ui, options: &mut Options, state: &mut State) {
+    ui.checkbox(&mut options.migration, "Migration");
+
+    ui.horizontal(|ui| {
+        ui.label("Migration Speed");
+        ui.add(egui::DragValue::new(&mut options.migration_speed).clamp_range(-5..=5));
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Route");
+        egui::ComboBox::from_id_source("migration_route")
+            .selected_text(options.migration_route.name())
+            .show_ui(ui, |ui| {
+                for route in MigrationRoute::ALL {
+                    ui.selectable_value(&mut options.migration_route, route, route.name());
+                }
+            });
+    });
+
+    match options.migration_route {
+        MigrationRoute::Heading => {
+            ui.horizontal(|ui| {
+                ui.label("Heading");
+                ui.add(
+                    egui::DragValue::new(&mut options.migration_heading)
+                        .suffix("°")
+                        .clamp_range(0.0..=360.0),
+                );
+            });
+        }
+        MigrationRoute::Waypoints => {
+            ui.checkbox(&mut options.migration_loop, "Loop");
+
+            let mut removed = None;
+            for (i, point) in options.migration_waypoints.iter_mut().enumerate() {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}", i + 1));
+                    ui.add(egui::DragValue::new(&mut point[0]));
+                    ui.add(egui::DragValue::new(&mut point[1]));
+                    if ui.small_button("✖").clicked() {
+                        removed = Some(i);
+                    }
+                });
+            }
+
+            if let Some(i) = removed {
+                options.migration_waypoints.remove(i);
+            }
+
+            if ui.button("Add Waypoint").clicked() {
+                // Continuing one border width on from the last waypoint
+                let step = options.border_size as f32 * 2.0;
+                let next = match options.migration_waypoints.last() {
+                    Some(last) => [last[0] + step, last[1]],
+                    None => [0.0, 0.0],
+                };
+                options.migration_waypoints.push(next);
+            }
+        }
+    }
+
+    if ui.button("Reset Migration").clicked() {
+        state.migration_distance = 0.0;
+        state.border_center = route_position(options, 0.0);
+    }
+
+    ui.label(format!(
+        "Migration: {:.0}, {:.0}",
+        state.border_center.x, state.border_center.y
+    ));
+}
+
+/// Marks the entity the migration route is drawn through.
+#[derive(Component)]
+pub struct MigrationMesh;
+
+pub fn spawn_migration_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn over the trails and under the boids
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, -0.25))
+        .insert(MigrationMesh);
+}
+
+/// Draws the route the border migrates along while migration is on.
+pub fn draw_migration_route(
+    mut migration_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<MigrationMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    options: Res<Options>,
+    state: Res<State>,
+    mut lines: Local<LineMesh>,
+) {
+    if !options.is_changed() && !state.is_changed() {
+        return;
+    }
+
+    let Ok((handle, mut visibility)) = migration_mesh.get_single_mut() else {
+        return;
+    };
+
+    lines.clear();
+    if options.migration {
+        match options.migration_route {
+            MigrationRoute::Heading => {
+                let angle = options.migration_heading.to_radians();
+                let dir = Vec2::new(angle.cos(), angle.sin());
+                let from = state.border_center;
+                let to = from + dir * options.border_size as f32;
+                lines.arrow(from, to, ROUTE_WIDTH, ROUTE_COLOR);
+            }
+            MigrationRoute::Waypoints => {
+                for (from, to) in segments(&options) {
+                    lines.segment(from, to, ROUTE_WIDTH, ROUTE_COLOR, ROUTE_COLOR);
+                }
+
+                for point in options.migration_waypoints.iter() {
+                    circle(
+                        &mut lines,
+                        Vec2::from(*point),
+                        2.0,
+                        ROUTE_WIDTH,
+                        ROUTE_COLOR,
+                    );
+                }
+            }
+        }
+
+        circle(
+            &mut lines,
+            state.border_center,
+            1.0,
+            ROUTE_WIDTH,
+            Color::WHITE,
+        );
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::Vec2;
+
+    use super::{advance, route_position, MigrationRoute};
+    use crate::{Options, State};
+
+    fn waypoints(points: &[[f32; 2]], looping: bool, speed: i32) -> Options {
+        Options {
+            migration_route: MigrationRoute::Waypoints,
+            migration_waypoints: points.to_vec(),
+            migration_loop: looping,
+            migration_speed: speed,
+            ..Default::default()
+        }
+    }
+
+    /// Border center after one step from `distance` along the route.
+    fn step_from(options: &Options, distance: f32) -> (f32, Vec2) {
+        let mut state = State {
+            migration_distance: distance,
+            ..Default::default()
+        };
+        advance(options, &mut state);
+        (state.migration_distance, state.border_center)
+    }
+
+    const SQUARE: [[f32; 2]; 4] = [[0.0, 0.0], [10.0, 0.0], [10.0, 10.0], [0.0, 10.0]];
+
+    #[test]
+    fn follows_the_segments() {
+        let options = waypoints(&SQUARE, false, 1);
+        assert_eq!(route_position(&options, 0.0), Vec2::ZERO);
+        assert_eq!(route_position(&options, 5.0), Vec2::new(5.0, 0.0));
+        assert_eq!(route_position(&options, 15.0), Vec2::new(10.0, 5.0));
+        assert_eq!(route_position(&options, 100.0), Vec2::new(0.0, 10.0));
+    }
+
+    #[test]
+    fn wraps_around_looping_routes() {
+        let options = waypoints(&SQUARE, true, 3);
+        assert_eq!(step_from(&options, 39.0), (2.0, Vec2::new(2.0, 0.0)));
+
+        // Going backwards wraps onto the segment closing the loop
+        let options = waypoints(&SQUARE, true, -3);
+        assert_eq!(step_from(&options, 1.0), (38.0, Vec2::new(0.0, 2.0)));
+    }
+
+    #[test]
+    fn stops_at_the_ends_of_open_routes() {
+        let options = waypoints(&SQUARE, false, 3);
+        assert_eq!(step_from(&options, 29.0), (30.0, Vec2::new(0.0, 10.0)));
+
+        let options = waypoints(&SQUARE, false, -3);
+        assert_eq!(step_from(&options, 1.0), (0.0, Vec2::ZERO));
+    }
+
+    #[test]
+    fn skips_zero_length_segments() {
+        let options = waypoints(&[[0.0, 0.0], [0.0, 0.0], [10.0, 0.0], [10.0, 0.0]], true, 1);
+        assert_eq!(route_position(&options, 0.0), Vec2::ZERO);
+        assert_eq!(route_position(&options, 5.0), Vec2::new(5.0, 0.0));
+        assert_eq!(step_from(&options, 14.5).1, Vec2::new(4.5, 0.0));
+
+        // A route that doesn't go anywhere stays on its only point
+        let options = waypoints(&[[3.0, 4.0], [3.0, 4.0]], true, 1);
+        assert_eq!(step_from(&options, 0.0), (0.0, Vec2::new(3.0, 4.0)));
+    }
+
+    #[test]
+    fn stays_at_the_origin_without_waypoints() {
+        for looping in [false, true] {
+            let options = waypoints(&[], looping, 1);
+            assert_eq!(route_position(&options, 5.0), Vec2::ZERO);
+            assert_eq!(step_from(&options, 5.0), (0.0, Vec2::ZERO));
+        }
+
+        let options = waypoints(&[[3.0, 4.0]], false, 1);
+        assert_eq!(step_from(&options, 0.0), (0.0, Vec2::new(3.0, 4.0)));
+    }
+}
@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use bevy::prelude::{Local, Query, Res, ResMut, Transform, Vec2, With, Without};
+use bevy::window::Windows;
+use bevy_egui::{egui, EguiContext};
+
+use crate::input::{Camera, CameraControls};
+use crate::{Boid, Options, State};
+
+/// Width and height of the minimap in points.
+const MINIMAP_SIZE: f32 = 180.0;
+
+/// Boids are binned into squares this many points wide, so the minimap stays cheap to draw with
+/// large flocks.
+const DOT_SIZE: f32 = 3.0;
+
+/// Corner map of the border, every boid and the area the camera shows. Clicking or dragging on it
+/// moves the camera there.
+pub fn minimap_gui(
+    boids: Query<&Transform, (With<Boid>, Without<Camera>)>,
+    cameras: Query<&Transform, With<Camera>>,
+    mut egui_ctx: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    options: Res<Options>,
+    state: Res<State>,
+    mut controls: ResMut<CameraControls>,
+    mut bins: Local<HashMap<(i32, i32), u32>>,
+) {
+    if !options.minimap {
+        return;
+    }
+
+    let (Ok(camera), Some(window)) = (cameras.get_single(), windows.get_primary()) else {
+        return;
+    };
+
+    let size = Vec2::splat(options.border_size as f32);
+    let border = (state.border_center - size, state.border_center + size);
+
+    let half_view = Vec2::new(window.width(), window.height()) * 0.5 * camera.scale.truncate();
+    let view_center = camera.translation.truncate();
+    let view = (view_center - half_view, view_center + half_view);
+
+    // Fitting the border, every boid and the viewport into a square
+    let (mut min, mut max) = (border.0.min(view.0), border.1.max(view.1));
+    for transform in boids.iter() {
+        let pos = transform.translation.truncate();
+        min = min.min(pos);
+        max = max.max(pos);
+    }
+
+    let extent = (max - min).max_element().max(1.0);
+    let center = (min + max) / 2.0;
+    let world_min = center - Vec2::splat(extent / 2.0);
+    let world_max = center + Vec2::splat(extent / 2.0);
+    let points_per_unit = MINIMAP_SIZE / extent;
+
+    bins.clear();
+    for transform in boids.iter() {
+        let pos = (transform.translation.truncate() - world_min) * points_per_unit / DOT_SIZE;
+        *bins.entry((pos.x as i32, pos.y as i32)).or_default() += 1;
+    }
+
+    let [r, g, b] = options.foreground_color.map(|it| (it * 255.0) as u8);
+
+    egui::Area::new("minimap")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                let (response, painter) = ui.allocate_painter(
+                    egui::vec2(MINIMAP_SIZE, MINIMAP_SIZE),
+                    egui::Sense::click_and_drag(),
+                );
+                let origin = response.rect.min;
+
+                // World y points up while screen y points down
+                let to_screen = |pos: Vec2| {
+                    origin
+                        + egui::vec2(
+                            (pos.x - world_min.x) * points_per_unit,
+                            (world_max.y - pos.y) * points_per_unit,
+                        )
+                };
+                let to_world = |pos: egui::Pos2| {
+                    Vec2::new(
+                        world_min.x + (pos.x - origin.x) / points_per_unit,
+                        world_max.y - (pos.y - origin.y) / points_per_unit,
+                    )
+                };
+
+                for ((x, y), count) in bins.iter() {
+                    let alpha = (96 + count * 32).min(255) as u8;
+                    let min = origin
+                        + egui::vec2(
+                            *x as f32 * DOT_SIZE,
+                            MINIMAP_SIZE - (*y + 1) as f32 * DOT_SIZE,
+                        );
+                    painter.rect_filled(
+                        egui::Rect::from_min_size(min, egui::vec2(DOT_SIZE, DOT_SIZE)),
+                        0.0,
+                        egui::Color32::from_rgba_unmultiplied(r, g, b, alpha),
+                    );
+                }
+
+                painter.rect_stroke(
+                    egui::Rect::from_two_pos(to_screen(border.0), to_screen(border.1)),
+                    0.0,
+                    egui::Stroke::new(1.0, egui::Color32::from_rgb(255, 230, 50)),
+                );
+                painter.rect_stroke(
+                    egui::Rect::from_two_pos(to_screen(view.0), to_screen(view.1)),
+                    0.0,
+                    egui::Stroke::new(1.0, egui::Color32::WHITE),
+                );
+
+                if response.clicked() || response.dragged() {
+                    if let Some(pos) = response.interact_pointer_pos() {
+                        controls.pan_to(camera, to_world(pos));
+                    }
+                }
+            });
+        });
+}
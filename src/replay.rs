@@ -0,0 +1,694 @@
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+
+use bevy::app::AppExit;
+use bevy::prelude::{
+    Commands, EventWriter, Local, Quat, Query, Res, ResMut, Transform, Vec2, Vec3,
+};
+use bevy_egui::{egui, EguiContext};
+use rand::Rng;
+use ron::ser::PrettyConfig;
+use serde::{Deserialize, Serialize};
+
+use boids::flow::VectorGrid;
+use boids::goal::Goal;
+
+use crate::flow_ui::FlowGrid;
+use crate::formation::Formation;
+use crate::{headless_app, Boid, BoidBundle, Options, SimRng, State, BOID_SCALE};
+
+/// Interaction with the simulation. These are queued and applied between ticks so that a
+/// recording can replay them at exactly the same point.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum SimEvent {
+    Spawn {
+        count: u32,
+        formation: Formation,
+        speed: f32,
+        /// Number of the spawned boids that are made leaders
+        leaders: u32,
+    },
+    SetVelocity {
+        id: u32,
+        vx: f32,
+        vy: f32,
+    },
+    Despawn {
+        id: u32,
+    },
+    SetLeader {
+        id: u32,
+        leader: bool,
+    },
+    /// Spawns boids spread over a circle, heading in `heading` radians or in random directions
+    Paint {
+        center: [f32; 2],
+        radius: f32,
+        count: u32,
+        heading: Option<f32>,
+        species: u8,
+    },
+    /// Despawns every boid within a circle
+    Erase {
+        center: [f32; 2],
+        radius: f32,
+    },
+    /// Places the point boids steer towards, or removes it
+    MoveAttractor(Option<[f32; 2]>),
+    /// Sets the direction leaders are steered in from the keyboard, or stops steering them
+    SteerLeaders(Option<[f32; 2]>),
+    PlaceGoal(Goal),
+    /// Removes the goal at an index of [`State::goals`]
+    RemoveGoal(usize),
+    /// Replaces the loaded flow field, or unloads it
+    LoadFlowGrid(Option<VectorGrid>),
+    SetOptions(Box<Options>),
+    Pause(bool),
+}
+
+impl SimEvent {
+    /// Spawns `count` boids in the formation and at the speed picked in the options.
+    pub fn spawn(count: u32, options: &Options) -> Self {
+        Self::Spawn {
+            count,
+            formation: options.formation,
+            speed: options.spawn_speed,
+            leaders: options.spawn_leaders,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct SimEvents(VecDeque<SimEvent>);
+
+impl SimEvents {
+    pub fn push(&mut self, event: SimEvent) {
+        self.0.push_back(event);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn drain(&mut self) -> impl Iterator<Item = SimEvent> + '_ {
+        self.0.drain(..)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotBoid {
+    pub id: u32,
+    pub flock_size: u32,
+    pub vx: f32,
+    pub vy: f32,
+    pub species: u8,
+    pub leader: bool,
+    pub translation: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+/// Everything needed to restart the simulation from a given tick.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub seed: u64,
+    pub tick: u64,
+    pub border_center: [f32; 2],
+    pub migration_distance: f32,
+    pub next_boid_id: u32,
+    pub attractor: Option<[f32; 2]>,
+    pub goals: Vec<Goal>,
+    pub leader_heading: Option<[f32; 2]>,
+    pub flow_grid: Option<VectorGrid>,
+    pub options: Options,
+    pub boids: Vec<SnapshotBoid>,
+}
+
+impl Snapshot {
+    pub fn capture<'a>(
+        boids: impl Iterator<Item = (&'a Boid, &'a Transform)>,
+        options: &Options,
+        state: &State,
+        flow_grid: &FlowGrid,
+        seed: u64,
+    ) -> Self {
+        let mut boids = boids
+            .map(|(boid, transform)| SnapshotBoid {
+                id: boid.id,
+                flock_size: boid.flock_size,
+                vx: boid.vx,
+                vy: boid.vy,
+                species: boid.species,
+                leader: boid.leader,
+                translation: transform.translation.to_array(),
+                rotation: transform.rotation.to_array(),
+            })
+            .collect::<Vec<_>>();
+        boids.sort_unstable_by_key(|it| it.id);
+
+        Self {
+            seed,
+            tick: state.tick,
+            border_center: state.border_center.to_array(),
+            migration_distance: state.migration_distance,
+            next_boid_id: state.next_boid_id,
+            attractor: state.attractor.map(|it| it.to_array()),
+            goals: state.goals.clone(),
+            leader_heading: state.leader_heading.map(|it| it.to_array()),
+            flow_grid: flow_grid.0.clone(),
+            options: options.clone(),
+            boids,
+        }
+    }
+
+    /// Spawns the recorded boids and resets the simulation state to match. Existing boids must
+    /// be despawned by the caller.
+    pub fn restore(
+        &self,
+        commands: &mut Commands,
+        options: &mut Options,
+        state: &mut State,
+        flow_grid: &mut FlowGrid,
+        rng: &mut SimRng,
+    ) {
+        for boid in self.boids.iter() {
+            commands.spawn_bundle(BoidBundle {
+                boid: Boid {
+                    id: boid.id,
+                    flock_size: boid.flock_size,
+                    vx: boid.vx,
+                    vy: boid.vy,
+                    species: boid.species,
+                    leader: boid.leader,
+                },
+                transform: Transform {
+                    translation: Vec3::from(boid.translation),
+                    rotation: Quat::from_array(boid.rotation),
+                    scale: BOID_SCALE,
+                },
+                ..Default::default()
+            });
+        }
+
+        options.apply_recorded(&self.options);
+        state.tick = self.tick;
+        state.border_center = Vec2::from(self.border_center);
+        state.migration_distance = self.migration_distance;
+        state.next_boid_id = self.next_boid_id;
+        state.attractor = self.attractor.map(Vec2::from);
+        state.goals = self.goals.clone();
+        state.leader_heading = self.leader_heading.map(Vec2::from);
+        flow_grid.0 = self.flow_grid.clone();
+        *rng = SimRng::new(self.seed);
+    }
+}
+
+/// A snapshot followed by every interaction made after it, tagged with the tick it was applied
+/// before.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Recording {
+    pub snapshot: Snapshot,
+    pub events: Vec<(u64, SimEvent)>,
+    pub end_tick: u64,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        ron::from_str(&contents).map_err(|e| format!("Failed to parse {}: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = ron::ser::to_string_pretty(self, PrettyConfig::default())
+            .map_err(|e| format!("Failed to serialize recording: {e}"))?;
+        std::fs::write(path, contents)
+            .map_err(|e| format!("Failed to write {}: {e}", path.display()))
+    }
+}
+
+#[derive(Default)]
+pub struct Recorder {
+    start_requested: bool,
+    stop_requested: bool,
+    current: Option<Recording>,
+    last_options: Option<Options>,
+    pub finished: Option<Recording>,
+}
+
+impl Recorder {
+    pub fn is_recording(&self) -> bool {
+        self.current.is_some()
+    }
+
+    pub fn request_start(&mut self) {
+        self.start_requested = true;
+    }
+
+    pub fn request_stop(&mut self) {
+        self.stop_requested = true;
+    }
+
+    /// Throws away the recording in progress, used when the timeline is rewound under it.
+    pub fn cancel(&mut self) {
+        self.current = None;
+        self.last_options = None;
+    }
+
+    pub fn event_count(&self) -> usize {
+        self.current.as_ref().map_or(0, |it| it.events.len())
+    }
+
+    pub fn record(&mut self, tick: u64, event: &SimEvent) {
+        if let Some(recording) = &mut self.current {
+            recording.events.push((tick, event.clone()));
+        }
+    }
+
+    /// Handles start and stop requests and logs any changes made to the options since the last
+    /// call.
+    pub fn update<'a>(
+        &mut self,
+        boids: impl Iterator<Item = (&'a Boid, &'a Transform)>,
+        options: &Options,
+        state: &State,
+        flow_grid: &FlowGrid,
+        rng: &mut SimRng,
+    ) {
+        if std::mem::take(&mut self.start_requested) {
+            // Reseeding so the recording doesn't depend on how much randomness was used before
+            let seed = rng.0.gen();
+            *rng = SimRng::new(seed);
+
+            self.current = Some(Recording {
+                snapshot: Snapshot::capture(boids, options, state, flow_grid, seed),
+                events: Vec::new(),
+                end_tick: state.tick,
+            });
+            self.last_options = Some(options.clone());
+        }
+
+        if std::mem::take(&mut self.stop_requested) {
+            if let Some(mut recording) = self.current.take() {
+                recording.end_tick = state.tick;
+                self.finished = Some(recording);
+            }
+
+            self.last_options = None;
+        }
+
+        let Some(last) = &self.last_options else {
+            return;
+        };
+
+        let mut events = Vec::new();
+        if last.paused != options.paused {
+            events.push(SimEvent::Pause(options.paused));
+        }
+
+        if last.simulation_only() != options.simulation_only() {
+            events.push(SimEvent::SetOptions(Box::new(options.clone())));
+        }
+
+        self.last_options = Some(options.clone());
+        for event in events.iter() {
+            self.record_merged(state.tick, event);
+        }
+    }
+
+    /// Records an event, replacing the options change recorded last if it was on the same tick
+    /// since only the latest one is ever applied. Dragging a slider while paused stays a single
+    /// event that way.
+    fn record_merged(&mut self, tick: u64, event: &SimEvent) {
+        if let Some(recording) = &mut self.current {
+            if let (Some((last_tick, SimEvent::SetOptions(last))), SimEvent::SetOptions(options)) =
+                (recording.events.last_mut(), event)
+            {
+                if *last_tick == tick {
+                    *last = options.clone();
+                    return;
+                }
+            }
+        }
+
+        self.record(tick, event);
+    }
+}
+
+#[derive(Default)]
+pub struct Replay {
+    recording: Option<Recording>,
+    next_event: usize,
+    start_requested: bool,
+    checksum: Option<u64>,
+}
+
+impl Replay {
+    pub fn play(&mut self, recording: Recording) {
+        self.recording = Some(recording);
+        self.next_event = 0;
+        self.start_requested = true;
+        self.checksum = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.recording = None;
+        self.start_requested = false;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    /// Checksum of the boids at the end of the last finished replay.
+    pub fn checksum(&self) -> Option<u64> {
+        self.checksum
+    }
+
+    pub fn take_start(&mut self) -> Option<&Snapshot> {
+        if !std::mem::take(&mut self.start_requested) {
+            return None;
+        }
+
+        self.recording.as_ref().map(|it| &it.snapshot)
+    }
+
+    pub fn end_tick(&self) -> Option<u64> {
+        self.recording.as_ref().map(|it| it.end_tick)
+    }
+
+    pub fn remaining_ticks(&self, tick: u64) -> Option<u64> {
+        self.end_tick().map(|it| it.saturating_sub(tick))
+    }
+
+    /// Queues every recorded event that has to be applied before the given tick.
+    pub fn enqueue_due(&mut self, tick: u64, events: &mut SimEvents) {
+        let Some(recording) = &self.recording else {
+            return;
+        };
+
+        while let Some((event_tick, event)) = recording.events.get(self.next_event) {
+            if *event_tick > tick {
+                break;
+            }
+
+            events.push(event.clone());
+            self.next_event += 1;
+        }
+    }
+
+    pub fn is_done(&self, tick: u64) -> bool {
+        match &self.recording {
+            Some(recording) => {
+                tick >= recording.end_tick && self.next_event >= recording.events.len()
+            }
+            None => false,
+        }
+    }
+
+    pub fn finish(&mut self, checksum: u64) {
+        self.recording = None;
+        self.checksum = Some(checksum);
+    }
+}
+
+/// FNV-1a hash over the state of every boid, ordered by id. Two runs of the same recording
+/// should always end with the same checksum.
+pub fn checksum<'a>(boids: impl Iterator<Item = (&'a Boid, &'a Transform)>) -> u64 {
+    let mut boids = boids.collect::<Vec<_>>();
+    boids.sort_unstable_by_key(|it| it.0.id);
+
+    let mut hash = 0xCBF2_9CE4_8422_2325u64;
+    let mut write = |bits: u32| {
+        for byte in bits.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01B3);
+        }
+    };
+
+    for (boid, transform) in boids {
+        write(boid.id);
+        write(boid.species as u32);
+        write(boid.leader as u32);
+        write(boid.vx.to_bits());
+        write(boid.vy.to_bits());
+        write(transform.translation.x.to_bits());
+        write(transform.translation.y.to_bits());
+    }
+
+    hash
+}
+
+/// Command line arguments, `--replay <file>` plays back a recording and `--headless` does so
+/// without opening a window. `--bench` runs the neighbor search benchmark instead and
+/// `--bench-render` the frame time benchmark.
+#[derive(Default)]
+pub struct Args {
+    pub replay: Option<PathBuf>,
+    pub headless: bool,
+    pub bench: bool,
+    pub bench_render: bool,
+    pub instanced: bool,
+}
+
+impl Args {
+    pub fn from_env() -> Self {
+        let mut args = Self::default();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--replay" => args.replay = iter.next().map(PathBuf::from),
+                "--headless" => args.headless = true,
+                "--bench" => args.bench = true,
+                "--bench-render" => args.bench_render = true,
+                "--instanced" => args.instanced = true,
+                _ => eprintln!("Warning: ignoring unknown argument {arg}"),
+            }
+        }
+
+        args
+    }
+}
+
+/// Replays a recording as fast as possible without rendering, then prints the final checksum.
+pub fn run_headless(recording: Recording) {
+    let mut replay = Replay::default();
+    replay.play(recording);
+
+    let mut app = headless_app(Options {
+        fast_forward: true,
+        fast_forward_ticks: 50,
+        history: false,
+        ..Default::default()
+    });
+
+    app.insert_resource(replay)
+        .add_system(exit_when_finished)
+        .run();
+}
+
+fn exit_when_finished(
+    replay: Res<Replay>,
+    state: Res<State>,
+    query: Query<&Boid>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let Some(checksum) = replay.checksum() else {
+        return;
+    };
+
+    println!(
+        "Replayed to tick {} with {} boids, checksum {checksum:016X}",
+        state.tick,
+        query.iter().len()
+    );
+    exit.send(AppExit);
+}
+
+pub struct SessionGui {
+    path: String,
+    status: String,
+}
+
+impl Default for SessionGui {
+    fn default() -> Self {
+        Self {
+            path: "recording.ron".to_owned(),
+            status: String::new(),
+        }
+    }
+}
+
+pub fn session_gui(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut options: ResMut<Options>,
+    mut recorder: ResMut<Recorder>,
+    mut replay: ResMut<Replay>,
+    mut gui: Local<SessionGui>,
+) {
+    egui::Window::new("Session")
+        .default_open(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Seed");
+                ui.add(egui::DragValue::new(&mut options.seed));
+            });
+
+            ui.separator();
+
+            if recorder.is_recording() {
+                ui.label(format!("Recording, {} events", recorder.event_count()));
+                if ui.button("Stop Recording").clicked() {
+                    recorder.request_stop();
+                }
+            } else if ui.button("Start Recording").clicked() {
+                recorder.request_start();
+            }
+
+            if let Some(end_tick) = replay.end_tick() {
+                ui.label(format!("Replaying until tick {end_tick}"));
+                if ui.button("Stop Replay").clicked() {
+                    replay.stop();
+                }
+            } else if let Some(checksum) = replay.checksum() {
+                ui.label(format!("Replay checksum: {checksum:016X}"));
+            }
+
+            ui.separator();
+
+            if ui.button("Replay Last Recording").clicked() {
+                match recorder.finished.clone() {
+                    Some(recording) => replay.play(recording),
+                    None => gui.status = "Nothing has been recorded yet".to_owned(),
+                }
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                // The web build has no file system, recordings only live in memory there
+                ui.text_edit_singleline(&mut gui.path);
+                ui.horizontal(|ui| {
+                    let path = PathBuf::from(&gui.path);
+
+                    if ui.button("Save").clicked() {
+                        gui.status = match &recorder.finished {
+                            Some(recording) => match recording.save(&path) {
+                                Ok(()) => format!("Saved to {}", path.display()),
+                                Err(e) => e,
+                            },
+                            None => "Nothing has been recorded yet".to_owned(),
+                        };
+                    }
+
+                    if ui.button("Load & Replay").clicked() {
+                        match Recording::load(&path) {
+                            Ok(recording) => {
+                                gui.status = format!("Loaded {}", path.display());
+                                replay.play(recording);
+                            }
+                            Err(e) => gui.status = e,
+                        }
+                    }
+                });
+            }
+
+            if !gui.status.is_empty() {
+                ui.label(&gui.status);
+            }
+        });
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::prelude::{App, Transform};
+
+    use super::{checksum, Recorder, Replay, SimEvent, SimEvents};
+    use crate::{headless_app, Boid, Options, SimulationCore, State};
+
+    /// Upper bound on the updates a replay gets to finish in.
+    const MAX_UPDATES: usize = 1000;
+
+    fn push(app: &mut App, event: SimEvent) {
+        app.world.resource_mut::<SimEvents>().push(event);
+    }
+
+    fn run(app: &mut App, updates: usize) {
+        for _ in 0..updates {
+            app.update();
+        }
+    }
+
+    fn boid_checksum(app: &mut App) -> u64 {
+        let mut query = app.world.query::<(&Boid, &Transform)>();
+        checksum(query.iter(&app.world))
+    }
+
+    #[test]
+    fn replays_recordings_to_the_same_checksum() {
+        for core in SimulationCore::ALL {
+            let options = Options {
+                paused: false,
+                seed: 3,
+                simulation_core: core,
+                wander_impact: 0.01,
+                noise: 5.0,
+                ..Default::default()
+            };
+
+            let mut app = headless_app(options.clone());
+            push(&mut app, SimEvent::spawn(150, &options));
+            run(&mut app, 10);
+
+            app.world.resource_mut::<Recorder>().request_start();
+            run(&mut app, 20);
+            push(
+                &mut app,
+                SimEvent::Paint {
+                    center: [10.0, -5.0],
+                    radius: 8.0,
+                    count: 20,
+                    heading: None,
+                    species: 1,
+                },
+            );
+            run(&mut app, 20);
+            push(
+                &mut app,
+                SimEvent::SetLeader {
+                    id: 3,
+                    leader: true,
+                },
+            );
+            app.world.resource_mut::<Options>().separation_impact = 0.08;
+            run(&mut app, 20);
+
+            // The recording ends on the tick it's stopped on, before the update ticks again
+            let expected = boid_checksum(&mut app);
+            let end_tick = app.world.resource::<State>().tick;
+            app.world.resource_mut::<Recorder>().request_stop();
+            app.update();
+            let recording = app
+                .world
+                .resource_mut::<Recorder>()
+                .finished
+                .take()
+                .expect("the recording should have been stopped");
+            assert_eq!(recording.end_tick, end_tick);
+
+            let mut replay = Replay::default();
+            replay.play(recording);
+            let mut app = headless_app(Options::default());
+            app.insert_resource(replay);
+            for _ in 0..MAX_UPDATES {
+                if app.world.resource::<Replay>().checksum().is_some() {
+                    break;
+                }
+
+                app.update();
+            }
+
+            let replayed = app.world.resource::<Replay>().checksum();
+            assert_eq!(replayed, Some(expected), "{core:?} core diverged");
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Flocking rules applied to one boid at a time, one neighbor at a time. This is what the ECS
+//! core runs with either neighbor backend, the structure of arrays core applies the same rules
+//! lane-wise.
+
+use bevy::math::Vec2;
+
+use crate::grid::GridEntry;
+use crate::soa::SoaParams;
+
+/// Velocity change caused by every rule on the last tick, in the order they're applied.
+#[derive(Debug, Default, Clone)]
+pub struct Forces {
+    pub separation: Vec2,
+    pub alignment: Vec2,
+    pub cohesion: Vec2,
+    pub border: Vec2,
+    pub attractor: Vec2,
+    pub wander: Vec2,
+    pub speed_limit: Vec2,
+    pub noise: Vec2,
+    /// Positions of the neighbors that were taken into account
+    pub neighbors: Vec<Vec2>,
+}
+
+impl Forces {
+    pub fn all(&self) -> [Vec2; 8] {
+        [
+            self.separation,
+            self.alignment,
+            self.cohesion,
+            self.border,
+            self.attractor,
+            self.wander,
+            self.speed_limit,
+            self.noise,
+        ]
+    }
+}
+
+/// State of a boid after a tick.
+#[derive(Debug, Clone, Copy)]
+pub struct Stepped {
+    pub pos: Vec2,
+    pub vel: Vec2,
+    pub flock_size: u32,
+}
+
+/// Computes the state of the boid with `id` after one tick.
+///
+/// `neighbors` has to call the closure it's given for every boid within the visibility range,
+/// including the boid itself. Only the first few flockmates are taken into account depending on
+/// the accuracy, so they have to be visited in the same order every time for a tick to be
+/// deterministic.
+pub fn step_boid(
+    id: u32,
+    boid: &GridEntry,
+    params: &SoaParams,
+    neighbors: impl FnOnce(&mut dyn FnMut(&GridEntry)),
+    forces: Option<&mut Forces>,
+) -> Stepped {
+    let pos = boid.pos;
+    let mut vel = boid.vel;
+    let record = forces.is_some();
+    let mut used_neighbors = Vec::new();
+
+    let mut close = Vec2::ZERO;
+    let mut flock_vel_sum = Vec2::ZERO;
+    let mut flock_pos_sum = Vec2::ZERO;
+    let mut flock_size = 0;
+    // Leaders count more than once towards the averages
+    let mut extra_weight = 0.0;
+
+    neighbors(&mut |other: &GridEntry| {
+        // Other species are kept apart from but aren't flockmates, so they aren't counted
+        if other.species != boid.species {
+            let d = pos - other.pos;
+            if d.length_squared() < params.separation_range && params.separation {
+                close += d;
+            }
+
+            return;
+        }
+
+        let i = flock_size;
+        flock_size += 1;
+        if i > params.accuracy {
+            return;
+        }
+
+        if record {
+            used_neighbors.push(other.pos);
+        }
+
+        let weight = params.leaders.weight_of(other.leader);
+        extra_weight += weight - 1.0;
+
+        // Applying separation if boids are close enough and cohesion if they are far enough
+        let d = pos - other.pos;
+        if d.length_squared() < params.separation_range && params.separation {
+            close += d;
+        } else if params.cohesion {
+            flock_pos_sum += other.pos * weight;
+        }
+
+        if params.alignment {
+            flock_vel_sum += other.vel * weight;
+        }
+    });
+
+    let mut last_velocity = vel;
+    let mut change = |vel: Vec2| {
+        let change = vel - last_velocity;
+        last_velocity = vel;
+        change
+    };
+
+    let mut alignment = Vec2::ZERO;
+    let mut cohesion = Vec2::ZERO;
+    if flock_size > 0 {
+        let weight = flock_size as f32 + extra_weight;
+        vel += (flock_vel_sum / weight - vel) * params.alignment_impact;
+        alignment = change(vel);
+
+        vel += (flock_pos_sum / weight - pos) * params.cohesion_impact;
+        cohesion = change(vel);
+    }
+
+    vel += close * params.separation_impact;
+    let separation = change(vel);
+
+    // Bounding boxes
+    if params.border {
+        let size = params.border_size;
+        let center = params.border_center;
+        if pos.x > center.x + size {
+            vel.x -= params.border_impact;
+        }
+
+        if pos.x < center.x - size {
+            vel.x += params.border_impact;
+        }
+
+        if pos.y > center.y + size {
+            vel.y -= params.border_impact;
+        }
+
+        if pos.y < center.y - size {
+            vel.y += params.border_impact;
+        }
+    }
+
+    let border = change(vel);
+
+    // Goals and leader steering count towards the attractor
+    if let Some(attractor) = params.attractor {
+        vel += (attractor - pos).normalize_or_zero() * params.attractor_impact;
+    }
+
+    for goal in params.goals.iter() {
+        vel += goal.pull(pos);
+    }
+
+    if boid.leader {
+        vel += params.leaders.steer(pos);
+    }
+
+    let attractor = change(vel);
+
+    vel += params.wander.wander(id, vel);
+    let wander = change(vel);
+
+    // Speed limits
+    if params.speed_limit {
+        let speed = vel.length();
+
+        if speed < params.min_speed {
+            vel = vel / speed * params.min_speed;
+        }
+
+        if speed > params.max_speed {
+            vel = vel / speed * params.max_speed;
+        }
+    }
+
+    let speed_limit = change(vel);
+
+    // Turning the heading keeps the speed within the limits
+    vel = params.wander.add_noise(id, vel);
+    let noise = change(vel);
+
+    if let Some(forces) = forces {
+        *forces = Forces {
+            separation,
+            alignment,
+            cohesion,
+            border,
+            attractor,
+            wander,
+            speed_limit,
+            noise,
+            neighbors: used_neighbors,
+        };
+    }
+
+    // The new position follows the velocity of the boid and the drift of the flow
+    Stepped {
+        pos: pos + vel + params.flow.sample(pos),
+        vel,
+        flock_size,
+    }
+}
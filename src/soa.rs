@@ -0,0 +1,642 @@
+use bevy::math::Vec2;
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+use bevy::tasks::{ComputeTaskPool, ParallelSlice};
+
+use crate::flow::FlowField;
+use crate::goal::Goal;
+use crate::grid::CellLayout;
+use crate::leader::LeaderParams;
+use crate::wander::WanderParams;
+
+/// Number of neighbors accumulated at once. Kept as plain arrays so the compiler turns the lane
+/// loops into SIMD instructions, on WASM through the `simd128` feature `.cargo/config.toml`
+/// enables.
+const LANES: usize = 8;
+
+/// Number of boids handed to each task when stepping in parallel.
+#[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+const PARALLEL_CHUNK_SIZE: usize = 1024;
+
+/// Flocking rules for a single tick, taken from the app's options.
+#[derive(Debug, Clone, Copy)]
+pub struct SoaParams<'a> {
+    pub visibility_range: f32,
+    pub accuracy: u32,
+
+    pub separation: bool,
+    pub separation_range: f32,
+    pub separation_impact: f32,
+
+    pub alignment: bool,
+    pub alignment_impact: f32,
+
+    pub cohesion: bool,
+    pub cohesion_impact: f32,
+
+    pub border: bool,
+    pub border_size: f32,
+    pub border_impact: f32,
+    /// Center of the border, moved by migration
+    pub border_center: Vec2,
+
+    /// Point every boid steers towards, if one is placed
+    pub attractor: Option<Vec2>,
+    pub attractor_impact: f32,
+    pub goals: &'a [Goal],
+
+    pub leaders: LeaderParams,
+
+    pub wander: WanderParams,
+
+    /// Drift added to the positions after the velocities are updated
+    pub flow: FlowField<'a>,
+
+    pub speed_limit: bool,
+    pub min_speed: f32,
+    pub max_speed: f32,
+}
+
+/// Flock stored as structure of arrays, every boid is an index into the arrays.
+///
+/// A step sorts copies of the positions and velocities by grid cell, so the neighbors of a boid
+/// are a few contiguous runs of every array and are accumulated [`LANES`] at a time.
+#[derive(Debug, Default)]
+pub struct SoaFlock {
+    pub px: Vec<f32>,
+    pub py: Vec<f32>,
+    pub vx: Vec<f32>,
+    pub vy: Vec<f32>,
+    pub species: Vec<u8>,
+    /// Ids of the boids, random numbers are drawn per id
+    pub id: Vec<u32>,
+    pub leader: Vec<bool>,
+    pub flock_size: Vec<u32>,
+
+    // Copies sorted by cell, kept around so their allocations are reused every tick
+    sorted_px: Vec<f32>,
+    sorted_py: Vec<f32>,
+    sorted_vx: Vec<f32>,
+    sorted_vy: Vec<f32>,
+    sorted_species: Vec<u8>,
+    sorted_id: Vec<u32>,
+    sorted_leader: Vec<bool>,
+}
+
+/// State of a boid after a step.
+#[derive(Debug, Clone, Copy)]
+struct Updated {
+    px: f32,
+    py: f32,
+    vx: f32,
+    vy: f32,
+    flock_size: u32,
+}
+
+/// Sums over the neighbors of a boid.
+#[derive(Debug, Default, Clone, Copy)]
+struct Accumulator {
+    close_dx: f32,
+    close_dy: f32,
+    flock_vx_sum: f32,
+    flock_vy_sum: f32,
+    flock_x_sum: f32,
+    flock_y_sum: f32,
+    /// Every neighbor in range, including the ones past the accuracy limit
+    flock_size: u32,
+    /// Weight the leaders among the neighbors add on top of counting once each
+    extra_weight: f32,
+}
+
+impl SoaFlock {
+    pub fn len(&self) -> usize {
+        self.px.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.px.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.px.clear();
+        self.py.clear();
+        self.vx.clear();
+        self.vy.clear();
+        self.species.clear();
+        self.id.clear();
+        self.leader.clear();
+        self.flock_size.clear();
+    }
+
+    pub fn push(&mut self, pos: Vec2, vel: Vec2, species: u8, id: u32, leader: bool) {
+        self.px.push(pos.x);
+        self.py.push(pos.y);
+        self.vx.push(vel.x);
+        self.vy.push(vel.y);
+        self.species.push(species);
+        self.id.push(id);
+        self.leader.push(leader);
+        self.flock_size.push(0);
+    }
+
+    /// Advances every boid by a single tick.
+    pub fn step(&mut self, params: &SoaParams) {
+        let positions = self
+            .px
+            .iter()
+            .zip(self.py.iter())
+            .map(|(x, y)| Vec2::new(*x, *y));
+        let Some(layout) = CellLayout::new(positions.clone(), params.visibility_range) else {
+            return;
+        };
+
+        let (cell_starts, order) = layout.sort(positions);
+        gather(&mut self.sorted_px, &self.px, &order);
+        gather(&mut self.sorted_py, &self.py, &order);
+        gather(&mut self.sorted_vx, &self.vx, &order);
+        gather(&mut self.sorted_vy, &self.vy, &order);
+        gather(&mut self.sorted_species, &self.species, &order);
+        gather(&mut self.sorted_id, &self.id, &order);
+        gather(&mut self.sorted_leader, &self.leader, &order);
+
+        let grid = SortedGrid {
+            layout,
+            cell_starts: &cell_starts,
+            px: &self.sorted_px,
+            py: &self.sorted_py,
+            vx: &self.sorted_vx,
+            vy: &self.sorted_vy,
+            species: &self.sorted_species,
+            id: &self.sorted_id,
+            leader: &self.sorted_leader,
+        };
+
+        // Boids are updated in cell order so the neighbors of consecutive boids stay in cache
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        let updated = (0..order.len())
+            .collect::<Vec<_>>()
+            .par_chunk_map(ComputeTaskPool::get(), PARALLEL_CHUNK_SIZE, |chunk| {
+                chunk
+                    .iter()
+                    .map(|i| grid.update(*i, params))
+                    .collect::<Vec<_>>()
+            })
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        #[cfg(not(all(feature = "parallel", not(target_arch = "wasm32"))))]
+        let updated = (0..order.len())
+            .map(|i| grid.update(i, params))
+            .collect::<Vec<_>>();
+
+        for (i, it) in order.iter().zip(updated) {
+            let i = *i as usize;
+            self.px[i] = it.px;
+            self.py[i] = it.py;
+            self.vx[i] = it.vx;
+            self.vy[i] = it.vy;
+            self.flock_size[i] = it.flock_size;
+        }
+    }
+}
+
+fn gather<T: Copy>(sorted: &mut Vec<T>, values: &[T], order: &[u32]) {
+    sorted.clear();
+    sorted.extend(order.iter().map(|i| values[*i as usize]));
+}
+
+/// Read only view of the flock sorted by cell, shared by all tasks during a step.
+struct SortedGrid<'a> {
+    layout: CellLayout,
+    cell_starts: &'a [u32],
+    px: &'a [f32],
+    py: &'a [f32],
+    vx: &'a [f32],
+    vy: &'a [f32],
+    species: &'a [u8],
+    id: &'a [u32],
+    leader: &'a [bool],
+}
+
+impl SortedGrid<'_> {
+    /// Computes the state of the boid at sorted position `i` after one tick.
+    fn update(&self, i: usize, params: &SoaParams) -> Updated {
+        let (px, py) = (self.px[i], self.py[i]);
+        let (mut vx, mut vy) = (self.vx[i], self.vy[i]);
+
+        let acc = self.accumulate(px, py, self.species[i], params);
+        if acc.flock_size > 0 {
+            let flock_size = acc.flock_size as f32 + acc.extra_weight;
+            vx += (acc.flock_vx_sum / flock_size - vx) * params.alignment_impact;
+            vy += (acc.flock_vy_sum / flock_size - vy) * params.alignment_impact;
+            vx += (acc.flock_x_sum / flock_size - px) * params.cohesion_impact;
+            vy += (acc.flock_y_sum / flock_size - py) * params.cohesion_impact;
+        }
+
+        vx += acc.close_dx * params.separation_impact;
+        vy += acc.close_dy * params.separation_impact;
+
+        // Bounding boxes
+        if params.border {
+            let size = params.border_size;
+            let center = params.border_center;
+            if px > center.x + size {
+                vx -= params.border_impact;
+            }
+
+            if px < center.x - size {
+                vx += params.border_impact;
+            }
+
+            if py > center.y + size {
+                vy -= params.border_impact;
+            }
+
+            if py < center.y - size {
+                vy += params.border_impact;
+            }
+        }
+
+        if let Some(attractor) = params.attractor {
+            let (dx, dy) = (attractor.x - px, attractor.y - py);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance > 0.0 {
+                vx += dx / distance * params.attractor_impact;
+                vy += dy / distance * params.attractor_impact;
+            }
+        }
+
+        for goal in params.goals.iter() {
+            let pull = goal.pull(Vec2::new(px, py));
+            vx += pull.x;
+            vy += pull.y;
+        }
+
+        if self.leader[i] {
+            let steer = params.leaders.steer(Vec2::new(px, py));
+            vx += steer.x;
+            vy += steer.y;
+        }
+
+        let id = self.id[i];
+        let wander = params.wander.wander(id, Vec2::new(vx, vy));
+        vx += wander.x;
+        vy += wander.y;
+
+        // Speed limits
+        if params.speed_limit {
+            let speed = (vx * vx + vy * vy).sqrt();
+
+            if speed < params.min_speed {
+                vx = (vx / speed) * params.min_speed;
+                vy = (vy / speed) * params.min_speed;
+            }
+
+            if speed > params.max_speed {
+                vx = (vx / speed) * params.max_speed;
+                vy = (vy / speed) * params.max_speed;
+            }
+        }
+
+        let vel = params.wander.add_noise(id, Vec2::new(vx, vy));
+        let (vx, vy) = (vel.x, vel.y);
+
+        let drift = params.flow.sample(Vec2::new(px, py));
+        Updated {
+            px: px + vx + drift.x,
+            py: py + vy + drift.y,
+            vx,
+            vy,
+            flock_size: acc.flock_size,
+        }
+    }
+
+    fn accumulate(&self, x: f32, y: f32, species: u8, params: &SoaParams) -> Accumulator {
+        let mut acc = Accumulator::default();
+        let mut lanes = Lanes::default();
+
+        let Some(range) = self
+            .layout
+            .cells_within(Vec2::new(x, y), params.visibility_range)
+        else {
+            return acc;
+        };
+
+        // Only the first few neighbors count towards the averages depending on the accuracy
+        let limit = params.accuracy + 1;
+        let rules = Rules::new(x, y, species, params);
+        for row in range.y0..=range.y1 {
+            let row = row * self.layout.cols();
+            let start = self.cell_starts[row + range.x0] as usize;
+            let end = self.cell_starts[row + range.x1 + 1] as usize;
+
+            // Full chunks go through the lanes as long as they can't cross the accuracy limit,
+            // everything else is accumulated one neighbor at a time
+            let mut i = start;
+            while i + LANES <= end && acc.flock_size + LANES as u32 <= limit {
+                acc.flock_size += lanes.add(&rules, self.chunk(i));
+                i += LANES;
+            }
+
+            for j in i..end {
+                let (px, py, vx, vy) = (self.px[j], self.py[j], self.vx[j], self.vy[j]);
+                let (species, leader) = (self.species[j], self.leader[j]);
+                rules.add_single(&mut acc, limit, px, py, vx, vy, species, leader);
+            }
+        }
+
+        lanes.reduce_into(&mut acc);
+        acc
+    }
+
+    fn chunk(&self, i: usize) -> Chunk {
+        let mut chunk = Chunk::default();
+        chunk.px.copy_from_slice(&self.px[i..i + LANES]);
+        chunk.py.copy_from_slice(&self.py[i..i + LANES]);
+        chunk.vx.copy_from_slice(&self.vx[i..i + LANES]);
+        chunk.vy.copy_from_slice(&self.vy[i..i + LANES]);
+        chunk.species.copy_from_slice(&self.species[i..i + LANES]);
+        chunk.leader.copy_from_slice(&self.leader[i..i + LANES]);
+        chunk
+    }
+}
+
+#[derive(Default)]
+struct Chunk {
+    px: [f32; LANES],
+    py: [f32; LANES],
+    vx: [f32; LANES],
+    vy: [f32; LANES],
+    species: [u8; LANES],
+    leader: [bool; LANES],
+}
+
+/// Rules applied to every neighbor of one boid, with the enabled flags turned into weights so
+/// they can be applied without branching.
+struct Rules {
+    x: f32,
+    y: f32,
+    species: u8,
+    radius_sq: f32,
+    separation_range: f32,
+    separation: f32,
+    alignment: f32,
+    cohesion: f32,
+    /// Weight a leader has on top of an ordinary flockmate
+    leader_extra: f32,
+}
+
+impl Rules {
+    fn new(x: f32, y: f32, species: u8, params: &SoaParams) -> Self {
+        let weight = |enabled: bool| if enabled { 1.0 } else { 0.0 };
+        Self {
+            x,
+            y,
+            species,
+            radius_sq: params.visibility_range * params.visibility_range,
+            separation_range: params.separation_range,
+            separation: weight(params.separation),
+            alignment: weight(params.alignment),
+            cohesion: weight(params.cohesion),
+            leader_extra: params.leaders.weight - 1.0,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_single(
+        &self,
+        acc: &mut Accumulator,
+        limit: u32,
+        px: f32,
+        py: f32,
+        vx: f32,
+        vy: f32,
+        species: u8,
+        leader: bool,
+    ) {
+        let dx = self.x - px;
+        let dy = self.y - py;
+        let dist_sq = dx * dx + dy * dy;
+        if dist_sq > self.radius_sq {
+            return;
+        }
+
+        // Other species are kept apart from but aren't flockmates, so they aren't counted
+        if species != self.species {
+            if dist_sq < self.separation_range && self.separation > 0.0 {
+                acc.close_dx += dx;
+                acc.close_dy += dy;
+            }
+
+            return;
+        }
+
+        acc.flock_size += 1;
+        if acc.flock_size > limit {
+            return;
+        }
+
+        let weight = if leader { 1.0 + self.leader_extra } else { 1.0 };
+        acc.extra_weight += weight - 1.0;
+
+        // Separation if boids are close enough and cohesion if they are far enough
+        if dist_sq < self.separation_range && self.separation > 0.0 {
+            acc.close_dx += dx;
+            acc.close_dy += dy;
+        } else {
+            acc.flock_x_sum += px * self.cohesion * weight;
+            acc.flock_y_sum += py * self.cohesion * weight;
+        }
+
+        acc.flock_vx_sum += vx * self.alignment * weight;
+        acc.flock_vy_sum += vy * self.alignment * weight;
+    }
+}
+
+/// Per lane partial sums, only added up once all neighbors of a boid have been visited.
+#[derive(Default)]
+struct Lanes {
+    close_dx: [f32; LANES],
+    close_dy: [f32; LANES],
+    flock_vx_sum: [f32; LANES],
+    flock_vy_sum: [f32; LANES],
+    flock_x_sum: [f32; LANES],
+    flock_y_sum: [f32; LANES],
+    extra_weight: [f32; LANES],
+}
+
+impl Lanes {
+    /// Accumulates a chunk of neighbors, returning how many flockmates were in range.
+    fn add(&mut self, rules: &Rules, chunk: Chunk) -> u32 {
+        let mut in_range = [0.0; LANES];
+        for l in 0..LANES {
+            let dx = rules.x - chunk.px[l];
+            let dy = rules.y - chunk.py[l];
+            let dist_sq = dx * dx + dy * dy;
+
+            let visible = if dist_sq <= rules.radius_sq { 1.0 } else { 0.0 };
+            let close = if dist_sq < rules.separation_range {
+                rules.separation
+            } else {
+                0.0
+            };
+            let same = if chunk.species[l] == rules.species {
+                1.0
+            } else {
+                0.0
+            };
+            let weight = if chunk.leader[l] {
+                1.0 + rules.leader_extra
+            } else {
+                1.0
+            };
+            let separate = visible * close;
+            let flockmate = visible * same;
+            let cohere = (flockmate - separate * same) * rules.cohesion * weight;
+            let align = flockmate * rules.alignment * weight;
+
+            self.close_dx[l] += dx * separate;
+            self.close_dy[l] += dy * separate;
+            self.flock_x_sum[l] += chunk.px[l] * cohere;
+            self.flock_y_sum[l] += chunk.py[l] * cohere;
+            self.flock_vx_sum[l] += chunk.vx[l] * align;
+            self.flock_vy_sum[l] += chunk.vy[l] * align;
+            self.extra_weight[l] += flockmate * (weight - 1.0);
+            in_range[l] = flockmate;
+        }
+
+        in_range.iter().sum::<f32>() as u32
+    }
+
+    fn reduce_into(&self, acc: &mut Accumulator) {
+        acc.close_dx += self.close_dx.iter().sum::<f32>();
+        acc.close_dy += self.close_dy.iter().sum::<f32>();
+        acc.flock_vx_sum += self.flock_vx_sum.iter().sum::<f32>();
+        acc.flock_vy_sum += self.flock_vy_sum.iter().sum::<f32>();
+        acc.flock_x_sum += self.flock_x_sum.iter().sum::<f32>();
+        acc.flock_y_sum += self.flock_y_sum.iter().sum::<f32>();
+        acc.extra_weight += self.extra_weight.iter().sum::<f32>();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::Vec2;
+    #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+    use bevy::tasks::{ComputeTaskPool, TaskPool};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::{SoaFlock, SoaParams};
+    use crate::flow::{FlowField, FlowPattern};
+    use crate::goal::GoalKind;
+    use crate::grid::{GridEntry, SpatialGrid};
+    use crate::leader::{LeaderParams, LeaderSteering};
+    use crate::rules::step_boid;
+    use crate::wander::WanderParams;
+
+    /// The structure of arrays core sums neighbors in a different order, so it only matches the
+    /// rules applied one neighbor at a time up to rounding.
+    const TOLERANCE: f32 = 1e-3;
+
+    #[test]
+    fn matches_the_ecs_rules() {
+        #[cfg(all(feature = "parallel", not(target_arch = "wasm32")))]
+        ComputeTaskPool::init(TaskPool::default);
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut entries = (0..500)
+            .map(|_| GridEntry {
+                pos: Vec2::new(rng.gen_range(-60.0..60.0), rng.gen_range(-60.0..60.0)),
+                vel: Vec2::new(rng.gen_range(-0.3..0.3), rng.gen_range(-0.3..0.3)),
+                species: rng.gen_range(0..2),
+                leader: rng.gen_bool(0.05),
+            })
+            .collect::<Vec<_>>();
+
+        let mut soa = SoaFlock::default();
+        for (id, entry) in entries.iter().enumerate() {
+            soa.push(entry.pos, entry.vel, entry.species, id as u32, entry.leader);
+        }
+
+        let mut goal = GoalKind::Roost.preset();
+        goal.position = [20.0, -10.0];
+        let goals = [goal];
+
+        for tick in 0..10 {
+            let params = SoaParams {
+                visibility_range: 10.0,
+                accuracy: 100,
+                separation: true,
+                separation_range: 2.0,
+                separation_impact: 0.05,
+                alignment: true,
+                alignment_impact: 0.05,
+                cohesion: true,
+                cohesion_impact: 0.0005,
+                border: true,
+                border_size: 50.0,
+                border_impact: 0.02,
+                border_center: Vec2::new(5.0, 0.0),
+                attractor: Some(Vec2::new(-30.0, 30.0)),
+                attractor_impact: 0.01,
+                goals: &goals,
+                leaders: LeaderParams {
+                    weight: 5.0,
+                    impact: 0.02,
+                    steering: LeaderSteering::Heading(Vec2::X),
+                },
+                wander: WanderParams {
+                    seed: 7,
+                    tick,
+                    impact: 0.01,
+                    radius: 0.5,
+                    rate: 0.02,
+                    noise: 0.05,
+                },
+                flow: FlowField {
+                    wind: Vec2::new(0.01, 0.0),
+                    pattern: FlowPattern::Curl,
+                    strength: 0.05,
+                    scale: 50.0,
+                    time: tick as f32 * 0.01,
+                    seed: 7,
+                    grid: None,
+                },
+                speed_limit: true,
+                min_speed: 0.1,
+                max_speed: 0.3,
+            };
+
+            let grid = SpatialGrid::new(&entries, params.visibility_range);
+            let stepped = entries
+                .iter()
+                .enumerate()
+                .map(|(id, entry)| {
+                    let visit = |f: &mut dyn FnMut(&GridEntry)| {
+                        grid.for_each_within(entry.pos, params.visibility_range, f)
+                    };
+                    step_boid(id as u32, entry, &params, visit, None)
+                })
+                .collect::<Vec<_>>();
+
+            for (entry, stepped) in entries.iter_mut().zip(stepped) {
+                entry.pos = stepped.pos;
+                entry.vel = stepped.vel;
+            }
+
+            soa.step(&params);
+        }
+
+        for (i, entry) in entries.iter().enumerate() {
+            let pos = Vec2::new(soa.px[i], soa.py[i]);
+            let vel = Vec2::new(soa.vx[i], soa.vy[i]);
+            assert!(
+                entry.pos.distance(pos) < TOLERANCE && entry.vel.distance(vel) < TOLERANCE,
+                "boid {i} is at {pos} moving {vel} but {} moving {} with the ECS rules",
+                entry.pos,
+                entry.vel,
+            );
+        }
+    }
+}
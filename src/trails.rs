@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::{
+    Added, Assets, Color, Commands, Component, Entity, Local, Mesh, Query, Res, ResMut, Transform,
+    Vec2, Visibility, With,
+};
+use bevy::sprite::Mesh2dHandle;
+
+use crate::lines::{line_mesh_bundle, LineMaterial, LineMesh};
+use crate::{boid_color, Boid, Options, State};
+
+/// Positions a boid went through on the most recent ticks, newest first.
+#[derive(Debug, Component, Default)]
+pub struct Trail(VecDeque<Vec2>);
+
+/// Marks the entity all trails are drawn through.
+#[derive(Component)]
+pub struct TrailMesh;
+
+pub fn spawn_trail_mesh(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    // Drawn just below the boids
+    commands
+        .spawn_bundle(line_mesh_bundle(&mut meshes, &material, -0.5))
+        .insert(TrailMesh);
+}
+
+pub fn init_trails(mut commands: Commands, query: Query<Entity, Added<Boid>>) {
+    for entity in query.iter() {
+        commands.entity(entity).insert(Trail::default());
+    }
+}
+
+/// Trail settings and tick the trail mesh was last built for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DrawnTrails {
+    /// Always 0 while trails are off
+    tick: u64,
+    trails: bool,
+    length: u32,
+    width: f32,
+    fade: f32,
+    calculate_color: bool,
+    foreground_color: [f32; 3],
+}
+
+/// Samples the position of every boid on frames the simulation ticked and rebuilds the trail
+/// mesh, fading every trail out towards its oldest position. Positions are sampled once per
+/// frame, so with several ticks per frame the trails skip the ticks in between.
+pub fn update_trails(
+    mut query: Query<(&Boid, &Transform, &mut Trail)>,
+    mut trail_mesh: Query<(&Mesh2dHandle, &mut Visibility), With<TrailMesh>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    options: Res<Options>,
+    state: Res<State>,
+    mut drawn: Local<Option<DrawnTrails>>,
+    mut lines: Local<LineMesh>,
+) {
+    let Ok((handle, mut visibility)) = trail_mesh.get_single_mut() else {
+        return;
+    };
+
+    // Only rebuilding when the boids moved or the trails look different, once trails are off
+    // nothing has to be rebuilt until they're turned back on
+    let key = DrawnTrails {
+        tick: if options.trails { state.tick } else { 0 },
+        trails: options.trails,
+        length: options.trail_length,
+        width: options.trail_width,
+        fade: options.trail_fade,
+        calculate_color: options.calculate_color,
+        foreground_color: options.foreground_color,
+    };
+    let last_tick = drawn.map(|it| it.tick);
+    if *drawn == Some(key) {
+        return;
+    }
+
+    *drawn = Some(key);
+
+    // Rewinding or turning trails off would otherwise leave trails that were never flown
+    let rewound = last_tick.map_or(false, |it| state.tick < it);
+    let length = options.trail_length as usize;
+    for (_, transform, mut trail) in query.iter_mut() {
+        if rewound || !options.trails {
+            trail.0.clear();
+        } else if last_tick != Some(state.tick) {
+            trail.0.push_front(transform.translation.truncate());
+        }
+
+        trail.0.truncate(length);
+    }
+
+    lines.clear();
+    if options.trails {
+        let last = options.trail_length.max(2) as f32 - 1.0;
+        let faded = |mut color: Color, i: usize| {
+            color.set_a(1.0 - options.trail_fade * (i as f32 / last));
+            color
+        };
+
+        for (boid, _, trail) in query.iter() {
+            let color = boid_color(boid, &options);
+            for (i, (from, to)) in trail.0.iter().zip(trail.0.iter().skip(1)).enumerate() {
+                lines.segment(
+                    *from,
+                    *to,
+                    options.trail_width,
+                    faded(color, i),
+                    faded(color, i + 1),
+                );
+            }
+        }
+    }
+
+    visibility.is_visible = !lines.is_empty();
+    if let Some(mesh) = meshes.get_mut(&handle.0) {
+        lines.write_to(mesh);
+    }
+}
@@ -0,0 +1,77 @@
+//! Random wandering and heading noise. The random numbers come from hashing the seed, the tick and
+//! the id of a boid rather than from a shared generator, so they don't depend on the order boids
+//! are updated in, which differs between the cores and between threads.
+
+use std::f32::consts::TAU;
+
+use bevy::math::Vec2;
+
+/// Separate streams of random numbers for every use, so they aren't correlated.
+const WANDER_STREAM: u64 = 1;
+const NOISE_STREAM: u64 = 2;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct WanderParams {
+    pub seed: u64,
+    pub tick: u64,
+    /// How strongly boids steer towards their wander target, 0 turns wandering off
+    pub impact: f32,
+    /// Radius of the circle the wander target moves on, relative to its distance ahead of the
+    /// boid
+    pub radius: f32,
+    /// How many times per tick the wander target picks a new direction to drift towards
+    pub rate: f32,
+    /// Largest angle in radians the heading is randomly turned by every tick
+    pub noise: f32,
+}
+
+impl WanderParams {
+    /// Steering towards a target on a circle ahead of the boid that drifts around it smoothly,
+    /// after Reynolds.
+    pub fn wander(&self, id: u32, vel: Vec2) -> Vec2 {
+        if self.impact == 0.0 {
+            return Vec2::ZERO;
+        }
+
+        let Some(dir) = vel.try_normalize() else {
+            return Vec2::ZERO;
+        };
+
+        // Interpolating between a random value per step keeps the target moving continuously
+        let t = self.tick as f64 * self.rate as f64;
+        let step = t.floor();
+        let f = (t - step) as f32;
+        let from = random(self.seed, step as u64, id, WANDER_STREAM);
+        let to = random(self.seed, step as u64 + 1, id, WANDER_STREAM);
+        let value = from + (to - from) * f * f * (3.0 - 2.0 * f);
+
+        let angle = (value * 2.0 - 1.0) * TAU;
+        let target = dir + Vec2::new(angle.cos(), angle.sin()) * self.radius;
+        target.normalize_or_zero() * self.impact
+    }
+
+    /// `vel` turned by a random angle of up to the noise amplitude either way.
+    pub fn add_noise(&self, id: u32, vel: Vec2) -> Vec2 {
+        if self.noise == 0.0 {
+            return vel;
+        }
+
+        let value = random(self.seed, self.tick, id, NOISE_STREAM);
+        let (sin, cos) = ((value * 2.0 - 1.0) * self.noise).sin_cos();
+        Vec2::new(vel.x * cos - vel.y * sin, vel.x * sin + vel.y * cos)
+    }
+}
+
+/// Number between 0 and 1 that only depends on the arguments, mixed with SplitMix64.
+fn random(seed: u64, tick: u64, id: u32, stream: u64) -> f32 {
+    let mut h = seed
+        ^ tick.wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (id as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ stream.wrapping_mul(0x1656_67B1_9E37_79F9);
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^= h >> 31;
+
+    // The top 24 bits fill the mantissa of an f32 exactly
+    (h >> 40) as f32 / (1 << 24) as f32
+}